@@ -176,3 +176,11 @@ CREATE TABLE IF NOT EXISTS special_condition_codes (
   description TEXT,
   unknown TEXT
 );
+CREATE TABLE IF NOT EXISTS rejected_records (
+  id INTEGER PRIMARY KEY,
+  update_id integer not null,
+  file_name varchar(40) not null,
+  line_number integer,
+  raw_line TEXT,
+  error TEXT not null
+);
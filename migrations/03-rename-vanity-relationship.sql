@@ -0,0 +1 @@
+ALTER TABLE amateurs RENAME COLUMN vainty_relationship TO vanity_relationship;
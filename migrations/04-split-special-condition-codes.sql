@@ -0,0 +1,15 @@
+ALTER TABLE special_condition_codes RENAME TO special_condition_codes_old;
+CREATE TABLE special_condition_codes (
+  code INTEGER PRIMARY KEY,
+  service varchar(2),
+  text_1 TEXT,
+  text_2 TEXT,
+  text_3 TEXT,
+  text_4 TEXT,
+  text_5 TEXT,
+  flag varchar(1)
+);
+-- The old text was mangled together, but it's better than nothing until the next weekly load
+INSERT INTO special_condition_codes (code, service, text_1, flag)
+  SELECT code, service, description, unknown FROM special_condition_codes_old;
+DROP TABLE special_condition_codes_old;
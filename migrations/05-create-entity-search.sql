@@ -0,0 +1,33 @@
+ALTER TABLE entities ADD COLUMN last_name_soundex varchar(4);
+CREATE INDEX IF NOT EXISTS idx_entities_last_name_soundex ON entities (last_name_soundex);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS entities_fts USING fts5(
+  entity_name,
+  first_name,
+  last_name,
+  city,
+  attention_line,
+  content = 'entities',
+  content_rowid = 'unique_system_identifier',
+  prefix = '2 3'
+);
+
+-- Keeps the index in sync as the loader writes to entities. INSERT OR REPLACE only fires the
+-- delete trigger with recursive_triggers on, which db::connect_options turns on.
+CREATE TRIGGER IF NOT EXISTS entities_fts_insert AFTER INSERT ON entities BEGIN
+  INSERT INTO entities_fts (rowid, entity_name, first_name, last_name, city, attention_line)
+    VALUES (new.unique_system_identifier, new.entity_name, new.first_name, new.last_name, new.city, new.attention_line);
+END;
+CREATE TRIGGER IF NOT EXISTS entities_fts_delete AFTER DELETE ON entities BEGIN
+  INSERT INTO entities_fts (entities_fts, rowid, entity_name, first_name, last_name, city, attention_line)
+    VALUES ('delete', old.unique_system_identifier, old.entity_name, old.first_name, old.last_name, old.city, old.attention_line);
+END;
+CREATE TRIGGER IF NOT EXISTS entities_fts_update
+  AFTER UPDATE OF entity_name, first_name, last_name, city, attention_line ON entities BEGIN
+  INSERT INTO entities_fts (entities_fts, rowid, entity_name, first_name, last_name, city, attention_line)
+    VALUES ('delete', old.unique_system_identifier, old.entity_name, old.first_name, old.last_name, old.city, old.attention_line);
+  INSERT INTO entities_fts (rowid, entity_name, first_name, last_name, city, attention_line)
+    VALUES (new.unique_system_identifier, new.entity_name, new.first_name, new.last_name, new.city, new.attention_line);
+END;
+
+INSERT INTO entities_fts (entities_fts) VALUES ('rebuild');
@@ -0,0 +1,4 @@
+-- Empty codes used to be stored as empty strings, which came back out as Unknown("")
+UPDATE amateurs SET operator_class = NULL WHERE operator_class = '';
+UPDATE amateurs SET previous_operator_class = NULL WHERE previous_operator_class = '';
+UPDATE entities SET applicant_type_code = NULL WHERE applicant_type_code = '';
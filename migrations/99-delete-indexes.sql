@@ -17,6 +17,7 @@ DROP INDEX IF EXISTS idx_entities_city;
 DROP INDEX IF EXISTS idx_entities_state;
 DROP INDEX IF EXISTS idx_entities_zip_code;
 DROP INDEX IF EXISTS idx_entities_frn;
+DROP INDEX IF EXISTS idx_entities_last_name_soundex;
 
 DROP INDEX IF EXISTS idx_headers_unique_system_identifier;
 DROP INDEX IF EXISTS idx_headers_call_sign;
@@ -1,32 +1,83 @@
+use artemis::codes::OperatorClass;
+use artemis::config::Config;
+use artemis::search::{SearchError, SearchPage, SearchParams, SearchResult};
+use artemis::sequential::SequenceCache;
+use axum::body::Body;
 use axum::extract::Query;
-use axum::http::StatusCode;
-use axum::http::Uri;
-use axum::response::{IntoResponse, Response};
+use axum::http::{header, Response, StatusCode};
+use axum::routing::post;
 use axum::Extension;
 use axum::{extract::Path, routing::get, Json, Router};
-use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, SqlitePool};
-use std::sync::Arc;
+use itertools::Itertools;
+use serde::Deserialize;
+use sqlx::SqlitePool;
+use std::collections::BTreeMap;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// The most calls `POST /api/v1/calls` will look up in one request
+const MAX_BULK_CALL_SIGNS: usize = 10_000;
+/// How often to check whether update-db has swapped in a new database
+const DB_WATCH_INTERVAL: Duration = Duration::from_secs(30);
 
 // static FILE_DIR: include_dir::Dir<'static> =
 //     include_dir::include_dir!("$CARGO_MANIFEST_DIR/web/dist");
 
+/// The pool handlers query through, which gets replaced when the database file is
+struct Database {
+    path: PathBuf,
+    pool: RwLock<SqlitePool>,
+    sequences: SequenceCache,
+}
+
+impl Database {
+    fn pool(&self) -> SqlitePool {
+        self.pool.read().unwrap().clone()
+    }
+}
+
 #[tokio::main]
 async fn main() {
-    let db = Arc::new(
-        SqlitePool::connect("sqlite://fcc.db")
-            .await
-            .expect("Error connecting to database"),
-    );
+    // Shares update-db's config, so both find the database in the same place
+    let config = Config::from_env().expect("Error reading config");
+    let pool = SqlitePool::connect_with(artemis::db::connect_options(&config.db_path))
+        .await
+        .expect("Error connecting to database");
+    artemis::db::require_latest_schema(&pool)
+        .await
+        .expect("Unsupported database schema");
 
-    let last_update = artemis::meta::get_last_update(&db, artemis::meta::UpdateType::Any)
+    let last_update = artemis::meta::get_last_update(&pool, artemis::meta::UpdateType::Any)
         .await
         .unwrap();
     println!("last update: {:?}", last_update);
 
+    let db = Arc::new(Database {
+        path: config.db_path,
+        pool: RwLock::new(pool),
+        sequences: SequenceCache::default(),
+    });
+    tokio::spawn(watch_database(db.clone()));
+
     let app = Router::new()
         .route("/api/v1/call/:call_sign", get(get_by_call_sign))
+        .route("/api/v1/calls", post(get_by_call_signs))
+        .route(
+            "/api/v1/call/:call_sign/history",
+            get(get_call_sign_history),
+        )
         .route("/api/v1/search", get(search))
+        .route("/api/v1/sequential", get(get_sequential))
+        .route("/api/v1/vanity", get(search_vanity_pattern))
+        .route("/api/v1/vanity/:call_sign", get(get_vanity_availability))
+        .route("/api/v1/license/:usi", get(get_license))
+        .route(
+            "/api/v1/license/:usi/conditions",
+            get(get_license_conditions),
+        )
         // .fallback(static_path)
         .layer(Extension(db));
 
@@ -37,6 +88,47 @@ async fn main() {
         .unwrap();
 }
 
+/// Reopens the pool whenever the database file is replaced, such as by `update-db --shadow`
+///
+/// The old pool is dropped rather than closed, since closing it would make requests that already
+/// have a handle to it fail to acquire a connection. Requests in flight during the swap finish
+/// against the old file, and its connections go away once the last of them is done.
+///
+/// A new file with a schema we don't expect isn't swapped in, so we keep serving the old one and
+/// try again next time, in case it's still being migrated.
+async fn watch_database(db: Arc<Database>) {
+    let mut inode = database_inode(&db.path);
+    let mut interval = tokio::time::interval(DB_WATCH_INTERVAL);
+    loop {
+        interval.tick().await;
+        let current = database_inode(&db.path);
+        if current.is_none() || current == inode {
+            continue;
+        }
+
+        let pool = match SqlitePool::connect_with(artemis::db::connect_options(&db.path)).await {
+            Ok(pool) => pool,
+            Err(err) => {
+                println!("Error reopening {}: {}", db.path.display(), err);
+                continue;
+            }
+        };
+        if let Err(err) = artemis::db::require_latest_schema(&pool).await {
+            println!("Not switching to new {}: {}", db.path.display(), err);
+            pool.close().await;
+            continue;
+        }
+
+        *db.pool.write().unwrap() = pool;
+        inode = current;
+        println!("database replaced, reopened {}", db.path.display());
+    }
+}
+
+fn database_inode(path: &std::path::Path) -> Option<u64> {
+    fs::metadata(path).ok().map(|metadata| metadata.ino())
+}
+
 // async fn static_path(uri: Uri) -> impl IntoResponse {
 //     let path = uri.path().trim_start_matches('/');
 //     let mime_type = mime_guess::from_path(path).first_or_text_plain();
@@ -74,48 +166,230 @@ async fn main() {
 // }
 
 async fn get_by_call_sign(
-    Extension(db): Extension<Arc<SqlitePool>>,
+    Extension(db): Extension<Arc<Database>>,
     Path(call_sign): Path<String>,
-) -> impl IntoResponse {
-    let call_sign = call_sign.to_uppercase();
-    Json(serde_json::json!(query_call_sign(&db, call_sign)
+) -> Result<Json<Vec<SearchResult>>, StatusCode> {
+    let call_sign = parse_call_sign(&call_sign)?;
+    query_call_sign(&db.pool(), call_sign.to_string())
         .await
-        .unwrap()))
+        .map(Json)
+        .map_err(internal_error)
+}
+
+/// Looks up lots of calls at once, returning `null` for any that are invalid or unlicensed
+async fn get_by_call_signs(
+    Extension(db): Extension<Arc<Database>>,
+    Json(call_signs): Json<Vec<String>>,
+) -> Result<Json<BTreeMap<String, Option<artemis::license::LicenseSummary>>>, StatusCode> {
+    if call_signs.len() > MAX_BULK_CALL_SIGNS {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let valid = call_signs
+        .iter()
+        .filter_map(|call_sign| call_sign.parse::<artemis::callsign::CallSign>().ok())
+        .map(|call_sign| call_sign.to_string())
+        .unique()
+        .collect::<Vec<_>>();
+    let summaries = artemis::license::get_summaries(&db.pool(), &valid)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(
+        call_signs
+            .into_iter()
+            .map(|requested| {
+                let summary = requested
+                    .parse::<artemis::callsign::CallSign>()
+                    .ok()
+                    .and_then(|call_sign| summaries.get(&call_sign.to_string()).cloned());
+                (requested, summary)
+            })
+            .collect(),
+    ))
+}
+
+async fn get_license(
+    Extension(db): Extension<Arc<Database>>,
+    Path(unique_system_identifier): Path<u32>,
+) -> Result<Json<artemis::license::License>, StatusCode> {
+    match artemis::license::get_license(&db.pool(), unique_system_identifier).await {
+        Ok(Some(license)) => Ok(Json(license)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(err) => Err(internal_error(err)),
+    }
+}
+
+async fn get_license_conditions(
+    Extension(db): Extension<Arc<Database>>,
+    Path(unique_system_identifier): Path<u32>,
+) -> Result<Json<artemis::license::Conditions>, StatusCode> {
+    let pool = db.pool();
+    if !artemis::license::exists(&pool, unique_system_identifier)
+        .await
+        .map_err(internal_error)?
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let conditions = artemis::license::get_conditions(&pool, unique_system_identifier)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(conditions))
 }
 
 #[derive(Debug, Deserialize)]
-struct SearchParams {
-    call_sign: Option<String>,
-    first_name: Option<String>,
-    last_name: Option<String>,
-    limit: Option<u32>,
+struct SequentialParams {
+    region_code: Option<i64>,
+    group_code: Option<String>,
+    /// How many upcoming calls to predict for each sequence
+    count: Option<usize>,
+}
+
+async fn get_sequential(
+    Extension(db): Extension<Arc<Database>>,
+    Query(params): Query<SequentialParams>,
+) -> Result<Json<Vec<artemis::sequential::Sequence>>, StatusCode> {
+    let count = params.count.unwrap_or(5).min(50);
+    let sequences = db
+        .sequences
+        .get_sequences(&db.pool(), count)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(
+        sequences
+            .into_iter()
+            .filter(|sequence| {
+                params
+                    .region_code
+                    .is_none_or(|region| sequence.region_code == region)
+            })
+            .filter(|sequence| {
+                params
+                    .group_code
+                    .as_ref()
+                    .is_none_or(|group| sequence.group_code.eq_ignore_ascii_case(group))
+            })
+            .collect(),
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+struct VanityParams {
+    /// The applicant's operator class code or name, such as `E` or `extra`
+    operator_class: Option<String>,
+}
+
+impl VanityParams {
+    fn operator_class(&self) -> Result<Option<OperatorClass>, StatusCode> {
+        match &self.operator_class {
+            None => Ok(None),
+            Some(class) => match OperatorClass::from_code(class) {
+                OperatorClass::Unknown(_) => Err(StatusCode::BAD_REQUEST),
+                class => Ok(Some(class)),
+            },
+        }
+    }
 }
+
+#[derive(Debug, Deserialize)]
+struct VanityPatternParams {
+    /// `?` for any letter and `#` for any digit, which have to be escaped in the URL
+    pattern: String,
+    #[serde(flatten)]
+    vanity: VanityParams,
+}
+
+/// Streams every available call matching a pattern as newline-delimited JSON
+async fn search_vanity_pattern(
+    Extension(db): Extension<Arc<Database>>,
+    Query(params): Query<VanityPatternParams>,
+) -> Result<Response<Body>, StatusCode> {
+    let operator_class = params.vanity.operator_class()?;
+    let candidates =
+        artemis::vanity::expand_pattern(&params.pattern).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let (mut sender, body) = Body::channel();
+    let pool = db.pool();
+    tokio::spawn(async move {
+        for batch in candidates.chunks(artemis::vanity::BATCH_SIZE) {
+            let results =
+                match artemis::vanity::check_batch(&pool, batch, operator_class.as_ref()).await {
+                    Ok(results) => results,
+                    Err(err) => {
+                        println!("Error checking vanity calls: {}", err);
+                        sender.abort();
+                        return;
+                    }
+                };
+            let mut lines = Vec::new();
+            for availability in results.iter().filter(|result| result.available) {
+                serde_json::to_writer(&mut lines, availability).unwrap();
+                lines.push(b'\n');
+            }
+            if !lines.is_empty() && sender.send_data(lines.into()).await.is_err() {
+                // The client went away
+                return;
+            }
+        }
+    });
+
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "application/x-ndjson")
+        .body(body)
+        .unwrap())
+}
+
+async fn get_vanity_availability(
+    Extension(db): Extension<Arc<Database>>,
+    Path(call_sign): Path<String>,
+    Query(params): Query<VanityParams>,
+) -> Result<Json<artemis::vanity::Availability>, StatusCode> {
+    let call_sign = parse_call_sign(&call_sign)?;
+    let operator_class = params.operator_class()?;
+    artemis::vanity::check(&db.pool(), &call_sign, operator_class.as_ref())
+        .await
+        .map(Json)
+        .map_err(internal_error)
+}
+
+/// Parses a call sign from the path, so garbage gets a 400 instead of a query
+fn parse_call_sign(call_sign: &str) -> Result<artemis::callsign::CallSign, StatusCode> {
+    call_sign.parse().map_err(|_| StatusCode::BAD_REQUEST)
+}
+
+fn internal_error(err: sqlx::Error) -> StatusCode {
+    println!("Error querying database: {}", err);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+async fn get_call_sign_history(
+    Extension(db): Extension<Arc<Database>>,
+    Path(call_sign): Path<String>,
+) -> Result<Json<Vec<artemis::history::TimelineEvent>>, StatusCode> {
+    let call_sign = parse_call_sign(&call_sign)?;
+    artemis::history::get_timeline(&db.pool(), &call_sign.to_string())
+        .await
+        .map(Json)
+        .map_err(internal_error)
+}
+
 async fn search(
-    Extension(db): Extension<Arc<SqlitePool>>,
+    Extension(db): Extension<Arc<Database>>,
     Query(params): Query<SearchParams>,
-) -> impl IntoResponse {
-    let res = query_search(&db, params).await.unwrap();
-    Json(serde_json::json!(res))
+) -> Result<Json<SearchPage>, StatusCode> {
+    match artemis::search::search(&db.pool(), &params).await {
+        Ok(page) => Ok(Json(page)),
+        Err(SearchError::InvalidCursor | SearchError::InvalidFilter(_)) => {
+            Err(StatusCode::BAD_REQUEST)
+        }
+        Err(SearchError::Sql(err)) => Err(internal_error(err)),
+    }
 }
 
-#[derive(Debug, FromRow, Serialize)]
-struct CallSign {
+async fn query_call_sign(
+    db: &SqlitePool,
     call_sign: String,
-    operator_class: String,
-    frn: String,
-    first_name: String,
-    mi: String,
-    last_name: String,
-    city: String,
-    state: String,
-    license_status: String,
-    grant_date: String,
-    expired_date: String,
-    cancellation_date: String,
-    call_count: i64,
-    call_history: String,
-}
-async fn query_call_sign(db: &SqlitePool, call_sign: String) -> Result<Vec<CallSign>, sqlx::Error> {
+) -> Result<Vec<SearchResult>, sqlx::Error> {
     let query_str = "SELECT
             amateurs.call_sign,
             amateurs.operator_class,
@@ -130,7 +404,8 @@ async fn query_call_sign(db: &SqlitePool, call_sign: String) -> Result<Vec<CallS
             headers.expired_date,
             headers.cancellation_date,
             count(amateurs.call_sign) AS call_count,
-            group_concat(amateurs.call_sign, ',') AS call_history
+            group_concat(amateurs.call_sign, ',') AS call_history,
+            NULL AS rank
         FROM entities
         JOIN amateurs
             ON amateurs.unique_system_identifier = entities.unique_system_identifier
@@ -146,57 +421,9 @@ async fn query_call_sign(db: &SqlitePool, call_sign: String) -> Result<Vec<CallS
         GROUP BY entities.frn
         ORDER BY headers.grant_date DESC";
 
-    let result = sqlx::query_as::<_, CallSign>(query_str)
+    let result = sqlx::query_as::<_, SearchResult>(query_str)
         .bind(call_sign)
         .fetch_all(db)
         .await?;
     Ok(result)
 }
-
-async fn query_search(
-    db: &SqlitePool,
-    search_params: SearchParams,
-) -> Result<Vec<CallSign>, sqlx::Error> {
-    let query_str = r#"SELECT
-            amateurs.call_sign,
-            amateurs.operator_class,
-            entities.frn,
-            entities.first_name,
-            entities.mi,
-            entities.last_name,
-            entities.city,
-            entities.state,
-            headers.license_status,
-            MAX(headers.grant_date) AS grant_date,
-            headers.expired_date,
-            headers.cancellation_date,
-            count(amateurs.call_sign) AS call_count,
-            group_concat(amateurs.call_sign, ',') AS call_history
-        FROM entities
-        JOIN amateurs
-            ON amateurs.unique_system_identifier = entities.unique_system_identifier
-        JOIN headers
-            ON amateurs.unique_system_identifier = headers.unique_system_identifier
-        WHERE
-            entities.frn IN (
-                SELECT frn
-                FROM entities
-                WHERE
-                    (?1 IS NULL OR call_sign LIKE ?1)
-                    AND (?2 IS NULL OR first_name LIKE ?2)
-                    AND (?3 IS NULL OR last_name LIKE ?3)
-            )
-            AND frn != ''
-        GROUP BY entities.frn
-        ORDER BY headers.grant_date DESC
-        LIMIT ?4"#;
-
-    let result = sqlx::query_as::<_, CallSign>(query_str)
-        .bind(search_params.call_sign)
-        .bind(search_params.first_name)
-        .bind(search_params.last_name)
-        .bind(search_params.limit.unwrap_or(20).min(100))
-        .fetch_all(db)
-        .await?;
-    Ok(result)
-}
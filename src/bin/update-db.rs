@@ -1,255 +1,299 @@
+use anyhow::{anyhow, Context};
 use chrono::{DateTime, Utc};
-use regex::Regex;
-use sqlx::sqlite::{SqliteConnectOptions, SqlitePool};
-use std::str::FromStr;
-use std::{fs, os::unix::prelude::MetadataExt, time::Duration};
-
-use artemis::file::{download_file, unzip_file};
-use artemis::{load, meta, Update};
-
-const WEEKLY_DUMP_URL: &str = "https://data.fcc.gov/download/pub/uls/complete/l_amat.zip";
-const SUNDAY_DUMP_URL: &str = "https://data.fcc.gov/download/pub/uls/daily/l_am_sun.zip";
-const MONDAY_DUMP_URL: &str = "https://data.fcc.gov/download/pub/uls/daily/l_am_mon.zip";
-const TUESDAY_DUMP_URL: &str = "https://data.fcc.gov/download/pub/uls/daily/l_am_tue.zip";
-const WEDNESDAY_DUMP_URL: &str = "https://data.fcc.gov/download/pub/uls/daily/l_am_wed.zip";
-const THURSDAY_DUMP_URL: &str = "https://data.fcc.gov/download/pub/uls/daily/l_am_thu.zip";
-const FRIDAY_DUMP_URL: &str = "https://data.fcc.gov/download/pub/uls/daily/l_am_fri.zip";
-const SATURDAY_DUMP_URL: &str = "https://data.fcc.gov/download/pub/uls/daily/l_am_sat.zip";
-const SPECIAL_CONDITIONS_URL: &str = "https://www.fcc.gov/file/20669/download";
-
-#[allow(dead_code)]
+use sqlx::sqlite::SqlitePool;
+use std::fs::{self, File, TryLockError};
+use std::io::ErrorKind;
+use std::path::Path;
+use zip::ZipArchive;
+
+use artemis::config::Config;
+use artemis::load::{self, load_table};
+use artemis::source::{Dump, DumpSource};
+use artemis::{
+    meta, Amateur, Comment, Entity, Header, History, LicenseAttachment, SpecialCondition,
+    SpecialConditionFreeForm, Update,
+};
+
+/// When each dump was last updated, according to the source
 #[derive(Debug)]
 struct FccUpdates {
     weekly: Option<DateTime<Utc>>,
-    sunday: Option<DateTime<Utc>>,
-    monday: Option<DateTime<Utc>>,
-    tuesday: Option<DateTime<Utc>>,
-    wednesday: Option<DateTime<Utc>>,
-    thursday: Option<DateTime<Utc>>,
-    friday: Option<DateTime<Utc>>,
-    saturday: Option<DateTime<Utc>>,
+    daily: Vec<(Dump, Option<DateTime<Utc>>)>,
 }
 impl FccUpdates {
-    fn new() -> Self {
-        Self {
-            weekly: get_last_updated_header(WEEKLY_DUMP_URL),
-            sunday: get_last_updated_header(SUNDAY_DUMP_URL),
-            monday: get_last_updated_header(MONDAY_DUMP_URL),
-            tuesday: get_last_updated_header(TUESDAY_DUMP_URL),
-            wednesday: get_last_updated_header(WEDNESDAY_DUMP_URL),
-            thursday: get_last_updated_header(THURSDAY_DUMP_URL),
-            friday: get_last_updated_header(FRIDAY_DUMP_URL),
-            saturday: get_last_updated_header(SATURDAY_DUMP_URL),
+    fn new(source: &dyn DumpSource) -> anyhow::Result<Self> {
+        let mut daily = Vec::new();
+        for dump in Dump::DAILY {
+            daily.push((dump, source.last_updated(dump)?));
         }
+        Ok(Self {
+            weekly: source.last_updated(Dump::Weekly)?,
+            daily,
+        })
     }
 
-    fn get_pending(&self, last_update: DateTime<Utc>) -> Vec<(DateTime<Utc>, String)> {
-        let mut pending = Vec::new();
+    fn get_pending(&self, last_update: DateTime<Utc>) -> Vec<(DateTime<Utc>, Dump)> {
+        let mut pending = self
+            .daily
+            .iter()
+            .filter_map(|(dump, updated)| match updated {
+                Some(updated) if *updated > last_update => Some((*updated, *dump)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
 
-        if let Some(sunday) = self.sunday {
-            if sunday > last_update {
-                pending.push((sunday, SUNDAY_DUMP_URL.to_string()));
-            }
-        }
-        if let Some(monday) = self.monday {
-            if monday > last_update {
-                pending.push((monday, MONDAY_DUMP_URL.to_string()));
-            }
-        }
-        if let Some(tuesday) = self.tuesday {
-            if tuesday > last_update {
-                pending.push((tuesday, TUESDAY_DUMP_URL.to_string()));
-            }
-        }
-        if let Some(wednesday) = self.wednesday {
-            if wednesday > last_update {
-                pending.push((wednesday, WEDNESDAY_DUMP_URL.to_string()));
-            }
-        }
-        if let Some(thursday) = self.thursday {
-            if thursday > last_update {
-                pending.push((thursday, THURSDAY_DUMP_URL.to_string()));
-            }
-        }
-        if let Some(friday) = self.friday {
-            if friday > last_update {
-                pending.push((friday, FRIDAY_DUMP_URL.to_string()));
-            }
-        }
-        if let Some(saturday) = self.saturday {
-            if saturday > last_update {
-                pending.push((saturday, SATURDAY_DUMP_URL.to_string()));
-            }
-        }
-
-        pending.sort_by(|a, b| a.0.cmp(&b.0));
+        pending.sort_by_key(|a| a.0);
 
         pending
     }
 }
 
-fn get_last_updated_header(url: &str) -> Option<DateTime<Utc>> {
-    let resp = ureq::head(url).call().expect("Error downloading file");
+/// Loads the weekly dump, replacing everything in the database
+///
+/// Every table is loaded in a single transaction along with the `updates` row, so if anything
+/// fails the database is left as it was.
+async fn load_weekly(source: &dyn DumpSource, db: &SqlitePool) -> anyhow::Result<()> {
+    let output_path = source
+        .fetch(Dump::Weekly)
+        .context("Error fetching weekly dump file")?
+        .ok_or_else(|| anyhow!("Weekly dump file not found"))?;
+    // The codes aren't part of the ULS dumps, so a source without them shouldn't stop an import
+    let conditions_path = source
+        .fetch(Dump::SpecialConditionCodes)
+        .context("Error fetching Special Conditions file")?;
 
-    assert!(resp.has("Content-Length"));
-    let len = resp
-        .header("Content-Length")
-        .unwrap_or("0")
-        .parse()
-        .unwrap_or(0);
+    let output_file = File::open(&output_path).context("Error opening weekly dump file")?;
+    let modified = output_file.metadata()?.modified()?;
+    let mut archive = ZipArchive::new(output_file).context("Error opening zip archive")?;
 
-    // This is the size given when there's no updates
-    if len <= 212 {
-        return None;
-    }
+    let mut transaction = db.begin().await?;
+    let update_id = meta::next_update_id(&mut transaction).await?;
 
-    match resp.header("Last-Modified") {
-        Some(last_mod) => match DateTime::parse_from_rfc2822(last_mod) {
-            Ok(dt) => Some(dt.into()),
-            Err(_) => None,
-        },
-        None => None,
+    artemis::db::delete_indexes(&mut transaction)
+        .await
+        .context("Error deleting indexes")?;
+
+    load_table::<Amateur>(&mut transaction, &mut archive, update_id, true).await?;
+    load_table::<Comment>(&mut transaction, &mut archive, update_id, true).await?;
+    load_table::<Entity>(&mut transaction, &mut archive, update_id, true).await?;
+    load_table::<Header>(&mut transaction, &mut archive, update_id, true).await?;
+    load_table::<History>(&mut transaction, &mut archive, update_id, true).await?;
+    load_table::<LicenseAttachment>(&mut transaction, &mut archive, update_id, true).await?;
+    load_table::<SpecialCondition>(&mut transaction, &mut archive, update_id, true).await?;
+    load_table::<SpecialConditionFreeForm>(&mut transaction, &mut archive, update_id, true).await?;
+
+    match conditions_path {
+        Some(path) => {
+            load::load_special_condition_codes(&mut transaction, &path, update_id, true).await?;
+        }
+        None => println!("No special condition codes found, skipping"),
     }
-}
 
-async fn load_weekly(db: &SqlitePool) -> chrono::DateTime<Utc> {
-    let output_file =
-        download_file(WEEKLY_DUMP_URL, None).expect("Error downloading weekly dump file");
-    // Hardcoding this file name because it might change and I don't want to deal with that
-    let _conditions_file =
-        download_file(SPECIAL_CONDITIONS_URL, Some("special_condition_codes.txt"))
-            .expect("Error downloading Special Conditions file");
-
-    unzip_file(&output_file).expect("Error unzipping file");
-    std::fs::remove_file("counts").expect("Error deleting counts file");
-
-    // This is somehow worse, newlines can either be \n (more common) OR \r\n.
-    // The first one is easy, if there's a newline without a preceeding carriage return, it's bad and should be gone
-    // CRLF is what's normally used, however the last character of every entry is either R, P, T, or |, so if there's a CRLF
-    // without one of those immediately before, yeet it
-    let conditions_regex = Regex::new(r"(([^\r]\n)|([^RPT\|]\r\n))").unwrap();
-    let conditions = fs::read_to_string("special_condition_codes.txt").expect("Error reading file");
-    fs::write(
-        "special_condition_codes.txt",
-        conditions_regex.replace_all(&conditions, " ").to_string(),
+    artemis::db::create_indexes(&mut transaction)
+        .await
+        .context("Error creating indexes")?;
+
+    meta::insert_update(
+        &mut transaction,
+        &Update {
+            id: update_id,
+            daily: false,
+            weekly: true,
+            date: DateTime::<Utc>::from(modified),
+        },
     )
-    .expect("Error writing file");
+    .await
+    .context("Error inserting weekly update")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Error committing weekly update")?;
 
-    artemis::db::delete_indexes(db).await.expect("Error deleting indexes");
+    Ok(())
+}
 
-    load::load_amateurs(db, true).await;
-    load::load_comments(db, true).await;
-    load::load_entities(db, true).await;
-    load::load_headers(db, true).await;
-    load::load_history(db, true).await;
-    load::load_license_attachments(db, true).await;
-    load::load_special_conditions(db, true).await;
-    load::load_special_conditions_free_form(db, true).await;
+/// Builds the weekly dump into a new database next to the live one, then renames it into place
+///
+/// The live database is left alone while the new one is loaded, indexed and checked, so the API
+/// can keep serving from it until the rename.
+async fn load_weekly_shadow(
+    source: &dyn DumpSource,
+    config: &Config,
+    db: &SqlitePool,
+) -> anyhow::Result<()> {
+    let shadow_db_path = config.shadow_db_path();
+    remove_file_if_exists(&shadow_db_path)?;
+    let shadow_db = SqlitePool::connect_with(
+        artemis::db::connect_options(&shadow_db_path).create_if_missing(true),
+    )
+    .await
+    .context("Error creating shadow database")?;
+    artemis::db::migrate(&shadow_db)
+        .await
+        .context("Error creating database")?;
 
-    load::load_special_condition_codes(db, true).await;
+    // Carry the update history over so update ids keep counting up
+    for update in meta::get_updates(db).await? {
+        meta::insert_update(&shadow_db, &update).await?;
+    }
 
-    artemis::db::create_indexes(db).await.expect("Error creating indexes");
+    load_weekly(source, &shadow_db).await?;
+    artemis::db::integrity_check(&shadow_db).await?;
+    shadow_db.close().await;
 
-    let meta = output_file.metadata().unwrap();
-    // std::fs::remove_file("l_amat.zip").expect("Error deleting l_amat.zip");
-    DateTime::<Utc>::from(
-        std::time::UNIX_EPOCH + Duration::from_secs(meta.mtime().try_into().unwrap()),
-    )
+    fs::rename(&shadow_db_path, &config.db_path).context("Error replacing database")?;
+    println!(
+        "Replaced {} with {}",
+        config.db_path.display(),
+        shadow_db_path.display()
+    );
+    Ok(())
 }
 
-async fn load_daily(url: &str, db: &SqlitePool) -> chrono::DateTime<Utc> {
-    let output_file = download_file(url, None).expect("Error downloading weekly dump file");
+/// Applies a daily dump on top of the database, in a single transaction like [`load_weekly`]
+async fn load_daily(source: &dyn DumpSource, dump: Dump, db: &SqlitePool) -> anyhow::Result<()> {
+    let output_path = source
+        .fetch(dump)
+        .with_context(|| format!("Error fetching {}", dump))?
+        .ok_or_else(|| anyhow!("{} not found", dump))?;
+
+    let output_file = File::open(&output_path).context("Error opening daily dump file")?;
+    let modified = output_file.metadata()?.modified()?;
+    let mut archive = ZipArchive::new(output_file).context("Error opening zip archive")?;
 
-    unzip_file(&output_file).expect("Error unzipping file");
-    std::fs::remove_file("counts").expect("Error deleting counts file");
+    let mut transaction = db.begin().await?;
+    let update_id = meta::next_update_id(&mut transaction).await?;
 
-    load::load_amateurs(db, false).await;
-    load::load_comments(db, false).await;
-    load::load_entities(db, false).await;
-    load::load_headers(db, false).await;
-    load::load_history(db, false).await;
-    load::load_license_attachments(db, false).await;
-    load::load_special_conditions(db, false).await;
-    load::load_special_conditions_free_form(db, false).await;
+    let replaced = load::delete_licenses(&mut transaction, &mut archive).await?;
+    println!("Replacing {} licenses from {}", replaced, dump);
 
-    let meta = output_file.metadata().unwrap();
+    load_table::<Amateur>(&mut transaction, &mut archive, update_id, false).await?;
+    load_table::<Comment>(&mut transaction, &mut archive, update_id, false).await?;
+    load_table::<Entity>(&mut transaction, &mut archive, update_id, false).await?;
+    load_table::<Header>(&mut transaction, &mut archive, update_id, false).await?;
+    load_table::<History>(&mut transaction, &mut archive, update_id, false).await?;
+    load_table::<LicenseAttachment>(&mut transaction, &mut archive, update_id, false).await?;
+    load_table::<SpecialCondition>(&mut transaction, &mut archive, update_id, false).await?;
+    load_table::<SpecialConditionFreeForm>(&mut transaction, &mut archive, update_id, false)
+        .await?;
 
-    // let file_name = parse_file_name_from_url(url);
-    // std::fs::remove_file(&file_name).unwrap_or_else(|_| panic!("Error deleting {}", file_name));
-    DateTime::<Utc>::from(
-        std::time::UNIX_EPOCH + Duration::from_secs(meta.mtime().try_into().unwrap()),
+    meta::insert_update(
+        &mut transaction,
+        &Update {
+            id: update_id,
+            daily: true,
+            weekly: false,
+            date: DateTime::<Utc>::from(modified),
+        },
     )
+    .await
+    .context("Error inserting daily update")?;
+
+    transaction
+        .commit()
+        .await
+        .context("Error committing daily update")?;
+
+    Ok(())
+}
+
+/// Takes an exclusive lock on `path`, creating it if needed, for as long as the file is kept open
+///
+/// The lock goes away with the process, so one left behind by a crash doesn't need cleaning up.
+fn lock_file(path: &Path) -> anyhow::Result<File> {
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .with_context(|| format!("Error opening {}", path.display()))?;
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(TryLockError::WouldBlock) => Err(anyhow!(
+            "{} is locked, is another update-db running?",
+            path.display()
+        )),
+        Err(TryLockError::Error(err)) => {
+            Err(err).with_context(|| format!("Error locking {}", path.display()))
+        }
+    }
+}
+
+fn remove_file_if_exists(path: &Path) -> anyhow::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != ErrorKind::NotFound => {
+            Err(err).with_context(|| format!("Error deleting {}", path.display()))
+        }
+        _ => Ok(()),
+    }
 }
 
 #[tokio::main]
-async fn main() {
-    let db = SqlitePool::connect_with(
-        SqliteConnectOptions::from_str("sqlite://fcc.db")
-            .expect("improperly formatted sqlite connection string, somehow")
-            .create_if_missing(true),
+async fn main() -> anyhow::Result<()> {
+    let config = Config::from_env().context("Error reading config")?;
+    fs::create_dir_all(&config.work_dir).context("Error creating work directory")?;
+    // Held until we exit, so another run can't share our downloads or shadow database
+    let _work_dir_lock = lock_file(&config.work_dir_lock_path())?;
+    let _db_lock = lock_file(&config.db_lock_path())?;
+    let source = artemis::source::from_location(&config.source, &config.work_dir);
+
+    let mut db = SqlitePool::connect_with(
+        artemis::db::connect_options(&config.db_path).create_if_missing(true),
     )
     .await
-    .expect("Error connecting to database");
+    .context("Error connecting to database")?;
 
-    artemis::db::create_db(&db).await.expect("Error creating database");
+    artemis::db::migrate(&db)
+        .await
+        .context("Error migrating database")?;
 
-    let fcc_updates = dbg!(FccUpdates::new());
+    let fcc_updates = FccUpdates::new(source.as_ref())?;
 
     let last_weekly = meta::get_last_update(&db, meta::UpdateType::Weekly)
         .await
-        .expect("Error getting last weekly update");
+        .context("Error getting last weekly update")?;
 
     // if this is the first time the database is being updated
-    if let Some(last_weekly) = last_weekly {
+    let weekly_pending = if let Some(last_weekly) = last_weekly {
         if fcc_updates.weekly.is_some() && fcc_updates.weekly.unwrap() > last_weekly.date {
             println!("New weekly update found, loading weekly dump");
-            let update_date = load_weekly(&db).await;
-            meta::insert_update(
-                &db,
-                &Update {
-                    id: 0, // placeholder
-                    daily: false,
-                    weekly: true,
-                    date: update_date,
-                },
-            )
-            .await
-            .expect("Error inserting weekly update");
+            true
+        } else {
+            false
         }
     } else {
         println!("No weekly updates found, loading weekly dump");
-        let update_date = load_weekly(&db).await;
-        meta::insert_update(
-            &db,
-            &Update {
-                id: 0, // placeholder
-                daily: false,
-                weekly: true,
-                date: update_date,
-            },
-        )
-        .await
-        .expect("Error inserting update");
+        true
+    };
+
+    // Build weekly dumps into a separate database instead of reloading the live one in place
+    if weekly_pending && config.shadow {
+        load_weekly_shadow(source.as_ref(), &config, &db).await?;
+        // Our connections still point at the file that was just replaced
+        db.close().await;
+        db = SqlitePool::connect_with(artemis::db::connect_options(&config.db_path))
+            .await
+            .context("Error connecting to database")?;
+    } else if weekly_pending {
+        load_weekly(source.as_ref(), &db).await?;
     }
 
     let last_update = meta::get_last_update(&db, meta::UpdateType::Any)
         .await
-        .expect("Error getting last update")
-        .expect("No updates found");
-
-    let pending = dbg!(fcc_updates.get_pending(dbg!(last_update.date)));
-    for update in pending {
-        let update_date = load_daily(&dbg!(update.1), &db).await;
-        meta::insert_update(
-            &db,
-            &Update {
-                id: 0, // placeholder
-                daily: true,
-                weekly: false,
-                date: update_date,
-            },
-        )
-        .await
-        .expect("Error inserting daily update");
+        .context("Error getting last update")?
+        .ok_or_else(|| anyhow!("No updates found"))?;
+
+    let pending = fcc_updates.get_pending(last_update.date);
+    println!(
+        "Last update was {}, {} daily dumps pending",
+        last_update.date,
+        pending.len()
+    );
+    for (updated, dump) in pending {
+        println!("Loading {} from {}", dump, updated);
+        load_daily(source.as_ref(), dump, &db).await?;
     }
+
+    Ok(())
 }
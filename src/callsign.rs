@@ -0,0 +1,208 @@
+//! Parsing for US amateur call signs
+//!
+//! A call is a one or two letter prefix starting with `A`, `K`, `N`, or `W`, a call district
+//! digit, and a one to three letter suffix. Two letter prefixes starting with `A` only go up to
+//! `AL`. The format and prefix decide which license classes the call can be issued to, see
+//! <https://www.fcc.gov/wireless/bureau-divisions/mobility-division/amateur-radio-service/amateur-sequential-call-sign>.
+
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// A valid US amateur call sign, split into its parts
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CallSign {
+    pub prefix: String,
+    pub digit: u8,
+    pub suffix: String,
+}
+
+/// The number of letters before and after the digit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum Format {
+    #[serde(rename = "1x1")]
+    OneByOne,
+    #[serde(rename = "1x2")]
+    OneByTwo,
+    #[serde(rename = "2x1")]
+    TwoByOne,
+    #[serde(rename = "1x3")]
+    OneByThree,
+    #[serde(rename = "2x2")]
+    TwoByTwo,
+    #[serde(rename = "2x3")]
+    TwoByThree,
+}
+
+/// The sequential call sign group, which decides which operator classes can hold a call
+///
+/// Group A is for Amateur Extra, B for Advanced, C for General, Technician and Technician Plus,
+/// and D for Novice and Technician.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub enum Group {
+    A,
+    B,
+    C,
+    D,
+}
+
+/// Where a call sign is from, going by its prefix and digit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Region {
+    Contiguous,
+    Alaska,
+    Hawaii,
+    /// Guam, American Samoa, the Northern Mariana Islands, and the other Pacific islands
+    Pacific,
+    /// Puerto Rico, the US Virgin Islands, and the other Caribbean islands
+    Caribbean,
+}
+
+/// Returned for anything that isn't a valid US amateur call sign
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidCallSign(pub String);
+
+impl fmt::Display for InvalidCallSign {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} is not a valid US amateur call sign", self.0)
+    }
+}
+
+impl std::error::Error for InvalidCallSign {}
+
+impl FromStr for CallSign {
+    type Err = InvalidCallSign;
+
+    /// Parses a call sign, ignoring case and surrounding whitespace
+    ///
+    /// # Examples
+    /// ```
+    /// use artemis::callsign::CallSign;
+    ///
+    /// let call: CallSign = "kl7abc".parse().unwrap();
+    /// assert_eq!(call.prefix, "KL");
+    /// assert_eq!(call.digit, 7);
+    /// assert_eq!(call.suffix, "ABC");
+    /// assert_eq!(call.to_string(), "KL7ABC");
+    ///
+    /// assert!("W1AWXX".parse::<CallSign>().is_err());
+    /// assert!("AM1AA".parse::<CallSign>().is_err());
+    /// assert!("'; DROP TABLE".parse::<CallSign>().is_err());
+    /// ```
+    fn from_str(call_sign: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidCallSign(call_sign.to_string());
+        let upper = call_sign.trim().to_ascii_uppercase();
+
+        let digit_index = upper
+            .find(|c: char| c.is_ascii_digit())
+            .ok_or_else(invalid)?;
+        let (prefix, rest) = upper.split_at(digit_index);
+        let suffix = &rest[1..];
+
+        if !(1..=2).contains(&prefix.len())
+            || !(1..=3).contains(&suffix.len())
+            || !prefix.bytes().all(|c| c.is_ascii_uppercase())
+            || !suffix.bytes().all(|c| c.is_ascii_uppercase())
+        {
+            return Err(invalid());
+        }
+        match prefix.as_bytes() {
+            [b'K' | b'N' | b'W'] | [b'K' | b'N' | b'W', _] | [b'A', b'A'..=b'L'] => {}
+            _ => return Err(invalid()),
+        }
+
+        Ok(CallSign {
+            prefix: prefix.to_string(),
+            digit: rest.as_bytes()[0] - b'0',
+            suffix: suffix.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for CallSign {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}", self.prefix, self.digit, self.suffix)
+    }
+}
+
+impl CallSign {
+    pub fn format(&self) -> Format {
+        match (self.prefix.len(), self.suffix.len()) {
+            (1, 1) => Format::OneByOne,
+            (1, 2) => Format::OneByTwo,
+            (2, 1) => Format::TwoByOne,
+            (1, 3) => Format::OneByThree,
+            (2, 2) => Format::TwoByTwo,
+            _ => Format::TwoByThree,
+        }
+    }
+
+    /// Returns the region the prefix and digit belong to
+    ///
+    /// # Examples
+    /// ```
+    /// use artemis::callsign::{CallSign, Region};
+    ///
+    /// let region = |call: &str| call.parse::<CallSign>().unwrap().region();
+    /// assert_eq!(region("W1AW"), Region::Contiguous);
+    /// assert_eq!(region("AL7X"), Region::Alaska);
+    /// assert_eq!(region("KH6ABC"), Region::Hawaii);
+    /// assert_eq!(region("KH2AB"), Region::Pacific);
+    /// assert_eq!(region("NP4A"), Region::Caribbean);
+    /// ```
+    pub fn region(&self) -> Region {
+        match self.prefix.as_bytes() {
+            [_, b'L'] => Region::Alaska,
+            [_, b'H'] if self.digit == 6 || self.digit == 7 => Region::Hawaii,
+            [_, b'H'] => Region::Pacific,
+            [_, b'P'] => Region::Caribbean,
+            _ => Region::Contiguous,
+        }
+    }
+
+    /// Returns the sequential call sign group the call belongs to
+    ///
+    /// 1x1 calls are only issued as special event calls, and 2x3 calls in the contiguous US only
+    /// come with `K` and `W` prefixes, so anything else isn't in any group. Outside the contiguous
+    /// US there's no room for a 1x3, so 2x3 calls starting with `N` or `W` (like `NL7ABC`) are
+    /// Group C, and ones starting with `K` (like `KL7ABC`) are Group D. 2x2 calls starting with `A`
+    /// are only Group A in the contiguous US (`AA` to `AK`), and the `AL` and `AH` ones elsewhere
+    /// are Group B.
+    ///
+    /// # Examples
+    /// ```
+    /// use artemis::callsign::{CallSign, Group};
+    ///
+    /// let group = |call: &str| call.parse::<CallSign>().unwrap().group();
+    /// assert_eq!(group("W1AW"), Some(Group::A));
+    /// assert_eq!(group("AA1AA"), Some(Group::A));
+    /// assert_eq!(group("KB1AA"), Some(Group::B));
+    /// assert_eq!(group("AL7AB"), Some(Group::B));
+    /// assert_eq!(group("AH6AB"), Some(Group::B));
+    /// assert_eq!(group("N1ABC"), Some(Group::C));
+    /// assert_eq!(group("KA1ABC"), Some(Group::D));
+    /// assert_eq!(group("NL7ABC"), Some(Group::C));
+    /// assert_eq!(group("WH6ABC"), Some(Group::C));
+    /// assert_eq!(group("KL7ABC"), Some(Group::D));
+    /// assert_eq!(group("KP4ABC"), Some(Group::D));
+    /// assert_eq!(group("K1A"), None);
+    /// assert_eq!(group("NA1ABC"), None);
+    /// ```
+    pub fn group(&self) -> Option<Group> {
+        let a_prefix = self.prefix.starts_with('A');
+        match self.format() {
+            Format::OneByOne => None,
+            Format::OneByTwo | Format::TwoByOne => Some(Group::A),
+            Format::TwoByTwo if a_prefix && self.region() == Region::Contiguous => Some(Group::A),
+            Format::TwoByTwo => Some(Group::B),
+            Format::OneByThree => Some(Group::C),
+            Format::TwoByThree if self.region() == Region::Contiguous => {
+                self.prefix.starts_with(['K', 'W']).then_some(Group::D)
+            }
+            Format::TwoByThree if self.prefix.starts_with(['N', 'W']) => Some(Group::C),
+            Format::TwoByThree if self.prefix.starts_with('K') => Some(Group::D),
+            Format::TwoByThree => None,
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! Enums for the single-letter (or two) codes the ULS uses for things like operator class
+//!
+//! Each one is stored as the FCC's code, but serialized as a readable name so the API doesn't have
+//! to explain what `P` means. Either one can be deserialized, so the API's own output round trips
+//! and filters like `?operator_class=extra` work. Codes we don't recognize are kept in `Unknown`
+//! rather than failing the whole record. Fields that can be left blank are `Option`s, so a blank
+//! column is `None` rather than `Unknown("")`.
+
+/// Defines an enum with an `Unknown(String)` fallback, mapping each variant to its code and name
+macro_rules! coded_enum {
+    (
+        $(#[$meta:meta])*
+        pub enum $name:ident {
+            $($variant:ident => ($code:literal, $label:literal),)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($variant,)*
+            Unknown(String),
+        }
+
+        impl $name {
+            /// Returns the variant for a code or a name, ignoring case, or `Unknown` if it isn't
+            /// one we know
+            pub fn from_code(code: &str) -> Self {
+                let code = code.trim();
+                $(
+                    if code.eq_ignore_ascii_case($code) || code.eq_ignore_ascii_case($label) {
+                        return $name::$variant;
+                    }
+                )*
+                $name::Unknown(code.to_string())
+            }
+
+            /// The FCC's code for this value
+            pub fn code(&self) -> &str {
+                match self {
+                    $($name::$variant => $code,)*
+                    $name::Unknown(code) => code,
+                }
+            }
+
+            /// A readable name for this value, or the raw code if it's `Unknown`
+            pub fn name(&self) -> &str {
+                match self {
+                    $($name::$variant => $label,)*
+                    $name::Unknown(code) => code,
+                }
+            }
+        }
+
+        impl From<&str> for $name {
+            fn from(code: &str) -> Self {
+                $name::from_code(code)
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(self.name())
+            }
+        }
+
+        impl serde::Serialize for $name {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.name())
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let code = std::borrow::Cow::<'de, str>::deserialize(deserializer)?;
+                Ok($name::from_code(&code))
+            }
+        }
+
+        impl sqlx::Type<sqlx::Sqlite> for $name {
+            fn type_info() -> sqlx::sqlite::SqliteTypeInfo {
+                <&str as sqlx::Type<sqlx::Sqlite>>::type_info()
+            }
+
+            fn compatible(ty: &sqlx::sqlite::SqliteTypeInfo) -> bool {
+                <&str as sqlx::Type<sqlx::Sqlite>>::compatible(ty)
+            }
+        }
+
+        impl<'q> sqlx::Encode<'q, sqlx::Sqlite> for $name {
+            fn encode_by_ref(
+                &self,
+                args: &mut Vec<sqlx::sqlite::SqliteArgumentValue<'q>>,
+            ) -> sqlx::encode::IsNull {
+                <String as sqlx::Encode<'q, sqlx::Sqlite>>::encode(self.code().to_string(), args)
+            }
+        }
+
+        impl<'r> sqlx::Decode<'r, sqlx::Sqlite> for $name {
+            fn decode(
+                value: sqlx::sqlite::SqliteValueRef<'r>,
+            ) -> Result<Self, sqlx::error::BoxDynError> {
+                let code = <&str as sqlx::Decode<'r, sqlx::Sqlite>>::decode(value)?;
+                Ok($name::from_code(code))
+            }
+        }
+    };
+}
+
+coded_enum! {
+    /// An amateur operator class, from `AM.dat`
+    ///
+    /// # Examples
+    /// ```
+    /// use artemis::codes::OperatorClass;
+    ///
+    /// assert_eq!(OperatorClass::from_code("P"), OperatorClass::TechnicianPlus);
+    /// assert_eq!(OperatorClass::from_code("extra"), OperatorClass::Extra);
+    /// assert_eq!(OperatorClass::Extra.code(), "E");
+    /// assert_eq!(OperatorClass::from_code("Z"), OperatorClass::Unknown("Z".to_string()));
+    ///
+    /// let json = serde_json::to_string(&OperatorClass::TechnicianPlus).unwrap();
+    /// assert_eq!(json, r#""technician_plus""#);
+    /// assert_eq!(
+    ///     serde_json::from_str::<OperatorClass>(&json).unwrap(),
+    ///     OperatorClass::TechnicianPlus
+    /// );
+    /// assert_eq!(serde_json::from_str::<Option<OperatorClass>>("null").unwrap(), None);
+    /// ```
+    pub enum OperatorClass {
+        Novice => ("N", "novice"),
+        Technician => ("T", "technician"),
+        TechnicianPlus => ("P", "technician_plus"),
+        General => ("G", "general"),
+        Advanced => ("A", "advanced"),
+        Extra => ("E", "extra"),
+    }
+}
+
+coded_enum! {
+    /// The status of a license, from `HD.dat`
+    pub enum LicenseStatus {
+        Active => ("A", "active"),
+        Cancelled => ("C", "cancelled"),
+        Expired => ("E", "expired"),
+        PendingLegalStatus => ("L", "pending_legal_status"),
+        ParentStationCancelled => ("P", "parent_station_cancelled"),
+        Terminated => ("T", "terminated"),
+        TermPending => ("X", "term_pending"),
+    }
+}
+
+coded_enum! {
+    /// Which party to a license an entity is, from `EN.dat`
+    pub enum EntityType {
+        TransfereeContact => ("CE", "transferee_contact"),
+        LicenseeContact => ("CL", "licensee_contact"),
+        AssignorContact => ("CR", "assignor_contact"),
+        LesseeContact => ("CS", "lessee_contact"),
+        Transferee => ("E", "transferee"),
+        Licensee => ("L", "licensee"),
+        Owner => ("O", "owner"),
+        Assignor => ("R", "assignor"),
+        Lessee => ("S", "lessee"),
+    }
+}
+
+coded_enum! {
+    /// What kind of applicant an entity is, from `EN.dat`
+    pub enum ApplicantType {
+        AmateurClub => ("A", "amateur_club"),
+        UnincorporatedAssociation => ("B", "unincorporated_association"),
+        Corporation => ("C", "corporation"),
+        LimitedLiabilityCorporation => ("D", "limited_liability_corporation"),
+        LimitedPartnership => ("E", "limited_partnership"),
+        LimitedLiabilityPartnership => ("F", "limited_liability_partnership"),
+        GovernmentalEntity => ("G", "governmental_entity"),
+        Other => ("H", "other"),
+        Individual => ("I", "individual"),
+        JointVenture => ("J", "joint_venture"),
+        MilitaryRecreation => ("M", "military_recreation"),
+        Consortium => ("O", "consortium"),
+        Partnership => ("P", "partnership"),
+        Races => ("R", "races"),
+        Trust => ("T", "trust"),
+        GeneralPartnership => ("U", "general_partnership"),
+    }
+}
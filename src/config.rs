@@ -0,0 +1,206 @@
+//! Settings for `update-db` and the API
+//!
+//! Each setting can come from a JSON config file, an environment variable, or a command line flag,
+//! with later ones overriding earlier ones. Anything not set keeps the old defaults of working in
+//! the current directory and loading into `fcc.db`. The API only uses the database path, so it
+//! serves whatever update-db loads into.
+
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+/// Where the updater keeps its files and gets its dumps from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// The database to load into, `--db` or `ARTEMIS_DB`
+    pub db_path: PathBuf,
+    /// Where downloads are kept, `--work-dir` or `ARTEMIS_WORK_DIR`
+    pub work_dir: PathBuf,
+    /// Where to get dumps from, see [`crate::source::from_location`]. `--source` or
+    /// `ARTEMIS_SOURCE`
+    pub source: String,
+    /// Whether to build weekly dumps into a new database, `--shadow`
+    pub shadow: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            db_path: PathBuf::from("fcc.db"),
+            work_dir: PathBuf::from("."),
+            source: "fcc".to_string(),
+            shadow: false,
+        }
+    }
+}
+
+/// The config file, where every setting is optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    db: Option<PathBuf>,
+    work_dir: Option<PathBuf>,
+    source: Option<String>,
+    shadow: Option<bool>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// A command line argument we don't know about
+    UnknownArgument(String),
+    /// The config file couldn't be read
+    Io {
+        file: String,
+        source: std::io::Error,
+    },
+    /// The config file isn't valid
+    Parse {
+        file: String,
+        source: serde_json::Error,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::UnknownArgument(arg) => write!(f, "Unknown argument {arg}"),
+            ConfigError::Io { file, source } => write!(f, "Error reading {file}: {source}"),
+            ConfigError::Parse { file, source } => write!(f, "Error parsing {file}: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::UnknownArgument(_) => None,
+            ConfigError::Io { source, .. } => Some(source),
+            ConfigError::Parse { source, .. } => Some(source),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config from the process's arguments and environment
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Config::load(std::env::args().skip(1), |name| std::env::var(name).ok())
+    }
+
+    /// Builds the config from a list of arguments, not including the program name, and a way to
+    /// look up environment variables
+    ///
+    /// The config file is given with `--config` or `ARTEMIS_CONFIG`.
+    ///
+    /// # Examples
+    /// ```
+    /// use artemis::config::Config;
+    /// use std::path::Path;
+    ///
+    /// let env = |name: &str| match name {
+    ///     "ARTEMIS_DB" => Some("/var/lib/artemis/fcc.db".to_string()),
+    ///     "ARTEMIS_WORK_DIR" => Some("/var/cache/artemis".to_string()),
+    ///     _ => None,
+    /// };
+    /// let args = ["--work-dir=/tmp/artemis", "--shadow"].map(String::from);
+    /// let config = Config::load(args, env).unwrap();
+    /// assert_eq!(config.db_path, Path::new("/var/lib/artemis/fcc.db"));
+    /// assert_eq!(config.work_dir, Path::new("/tmp/artemis"));
+    /// assert_eq!(config.source, "fcc");
+    /// assert!(config.shadow);
+    ///
+    /// assert!(Config::load(["--bogus".to_string()], |_| None).is_err());
+    /// ```
+    pub fn load(
+        args: impl IntoIterator<Item = String>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError> {
+        let args = args.into_iter().collect::<Vec<_>>();
+        let flag = |name: &str| {
+            let prefix = format!("--{}=", name);
+            args.iter()
+                .rev()
+                .find_map(|arg| arg.strip_prefix(&prefix).map(String::from))
+        };
+
+        for arg in &args {
+            let known = arg == "--shadow"
+                || ["--config=", "--db=", "--work-dir=", "--source="]
+                    .iter()
+                    .any(|prefix| arg.starts_with(prefix));
+            if !known {
+                return Err(ConfigError::UnknownArgument(arg.clone()));
+            }
+        }
+
+        let file = match flag("config").or_else(|| env("ARTEMIS_CONFIG")) {
+            Some(path) => {
+                let contents = fs::read_to_string(&path).map_err(|source| ConfigError::Io {
+                    file: path.clone(),
+                    source,
+                })?;
+                serde_json::from_str(&contents)
+                    .map_err(|source| ConfigError::Parse { file: path, source })?
+            }
+            None => ConfigFile::default(),
+        };
+
+        let defaults = Config::default();
+        Ok(Config {
+            db_path: flag("db")
+                .or_else(|| env("ARTEMIS_DB"))
+                .map(PathBuf::from)
+                .or(file.db)
+                .unwrap_or(defaults.db_path),
+            work_dir: flag("work-dir")
+                .or_else(|| env("ARTEMIS_WORK_DIR"))
+                .map(PathBuf::from)
+                .or(file.work_dir)
+                .unwrap_or(defaults.work_dir),
+            source: flag("source")
+                .or_else(|| env("ARTEMIS_SOURCE"))
+                .or(file.source)
+                .unwrap_or(defaults.source),
+            shadow: args.iter().any(|arg| arg == "--shadow") || file.shadow.unwrap_or(false),
+        })
+    }
+
+    /// Where the shadow database is built, next to the live one
+    ///
+    /// # Examples
+    /// ```
+    /// use artemis::config::Config;
+    /// use std::path::Path;
+    ///
+    /// assert_eq!(Config::default().shadow_db_path(), Path::new("fcc.db.new"));
+    /// ```
+    pub fn shadow_db_path(&self) -> PathBuf {
+        self.db_path_with_extension(".new")
+    }
+
+    /// The file update-db locks while it's loading into the database, so two of them can't build
+    /// the same shadow database at once
+    ///
+    /// # Examples
+    /// ```
+    /// use artemis::config::Config;
+    /// use std::path::Path;
+    ///
+    /// assert_eq!(Config::default().db_lock_path(), Path::new("fcc.db.lock"));
+    /// ```
+    pub fn db_lock_path(&self) -> PathBuf {
+        self.db_path_with_extension(".lock")
+    }
+
+    /// The file update-db locks while it's downloading into the work directory, so two of them
+    /// can't write to the same partial downloads at once
+    pub fn work_dir_lock_path(&self) -> PathBuf {
+        self.work_dir.join("update-db.lock")
+    }
+
+    fn db_path_with_extension(&self, extension: &str) -> PathBuf {
+        let mut path = self.db_path.clone().into_os_string();
+        path.push(extension);
+        PathBuf::from(path)
+    }
+}
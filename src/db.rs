@@ -1,20 +1,234 @@
-use sqlx::SqlitePool;
+use anyhow::{bail, Context};
+use chrono::Utc;
+use libsqlite3_sys as ffi;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode};
+use sqlx::{SqliteConnection, SqlitePool};
+use std::ffi::{c_char, c_int};
+use std::path::Path;
+use std::ptr;
+
+use crate::search::soundex;
 
-const CREATE_DB_SQL: &str = include_str!("../migrations/01-create-db.sql");
 const CREATE_INDEXES_SQL: &str = include_str!("../migrations/02-create-indexes.sql");
 const DELETE_INDEXES_SQL: &str = include_str!("../migrations/99-delete-indexes.sql");
+const SUSPEND_ENTITY_SEARCH_SQL: &str = include_str!("sql/suspend-entity-search.sql");
+const REBUILD_ENTITY_SEARCH_SQL: &str = include_str!("sql/rebuild-entity-search.sql");
+
+const CREATE_SCHEMA_VERSION_SQL: &str = "CREATE TABLE IF NOT EXISTS schema_version (
+  version INTEGER PRIMARY KEY,
+  applied_at datetime NOT NULL
+)";
+
+/// Every schema migration, as the version number from the start of its file name and its SQL
+///
+/// These are applied in order to bring a database up to date, so new migrations should only ever
+/// be added to the end. `99-delete-indexes.sql` isn't one, it's just used while loading.
+const MIGRATIONS: &[(u32, &str)] = &[
+    (1, include_str!("../migrations/01-create-db.sql")),
+    (2, CREATE_INDEXES_SQL),
+    (
+        3,
+        include_str!("../migrations/03-rename-vanity-relationship.sql"),
+    ),
+    (
+        4,
+        include_str!("../migrations/04-split-special-condition-codes.sql"),
+    ),
+    (5, include_str!("../migrations/05-create-entity-search.sql")),
+    (6, include_str!("../migrations/06-null-empty-codes.sql")),
+];
+
+/// The migration that added `entities.last_name_soundex`, which has to be filled in from Rust
+const SOUNDEX_MIGRATION: u32 = 5;
 
-pub async fn create_db(db: &SqlitePool) -> anyhow::Result<()> {
-    sqlx::query(CREATE_DB_SQL).execute(db).await?;
+/// The options everything that opens the database should use
+///
+/// This uses a rollback journal rather than WAL, so there are never `-wal` or `-shm` files left
+/// next to the database. A new database renamed over the live one would otherwise pick up the
+/// old one's WAL.
+///
+/// Recursive triggers are on so the rows `INSERT OR REPLACE` deletes still fire the triggers
+/// keeping `entities_fts` in sync.
+pub fn connect_options(path: impl AsRef<Path>) -> SqliteConnectOptions {
+    SqliteConnectOptions::new()
+        .filename(path)
+        .journal_mode(SqliteJournalMode::Delete)
+        .pragma("recursive_triggers", "ON")
+}
+
+/// The schema version this binary expects, which is the version of the last migration
+pub fn latest_schema_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |(version, _)| *version)
+}
+
+/// Returns the version of the last migration applied to the database, or 0 for a new one
+///
+/// This doesn't write anything, so it's safe to call on a database something else is updating.
+pub async fn schema_version(db: &SqlitePool) -> Result<u32, sqlx::Error> {
+    let (exists,): (bool,) = sqlx::query_as(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version')",
+    )
+    .fetch_one(db)
+    .await?;
+    if !exists {
+        return Ok(0);
+    }
+    let (version,): (u32,) = sqlx::query_as("SELECT COALESCE(MAX(version), 0) FROM schema_version")
+        .fetch_one(db)
+        .await?;
+    Ok(version)
+}
+
+/// Returns an error if the database has migrations this binary doesn't know about
+pub async fn check_schema_version(db: &SqlitePool) -> anyhow::Result<u32> {
+    let version = schema_version(db).await?;
+    if version > latest_schema_version() {
+        bail!(
+            "Database schema version {} is newer than the latest this binary supports ({})",
+            version,
+            latest_schema_version()
+        );
+    }
+    Ok(version)
+}
+
+/// Returns an error unless the database has exactly the migrations this binary knows about
+///
+/// This is for things that only read the database, like the API, which can't migrate it and
+/// would otherwise fail on the first query that touches a missing table or column.
+pub async fn require_latest_schema(db: &SqlitePool) -> anyhow::Result<()> {
+    let version = check_schema_version(db).await?;
+    if version < latest_schema_version() {
+        bail!(
+            "Database schema version {} is older than {}, run update-db to migrate it",
+            version,
+            latest_schema_version()
+        );
+    }
     Ok(())
 }
 
-pub async fn create_indexes(db: &SqlitePool) -> anyhow::Result<()> {
-    sqlx::query(CREATE_INDEXES_SQL).execute(db).await?;
+/// Applies every migration the database hasn't had yet, each in its own transaction
+///
+/// This refuses to touch a database with a newer schema than this binary knows about, since it
+/// was presumably migrated by a newer version that has moved on from the tables we'd write to.
+pub async fn migrate(db: &SqlitePool) -> anyhow::Result<()> {
+    sqlx::query(CREATE_SCHEMA_VERSION_SQL).execute(db).await?;
+    let current = check_schema_version(db).await?;
+
+    for (version, sql) in MIGRATIONS.iter().filter(|(version, _)| *version > current) {
+        let mut transaction = db.begin().await?;
+        sqlx::query(sql)
+            .execute(&mut transaction)
+            .await
+            .with_context(|| format!("Error applying migration {}", version))?;
+        if *version == SOUNDEX_MIGRATION {
+            fill_last_name_soundex(&mut transaction).await?;
+        }
+        sqlx::query("INSERT INTO schema_version (version, applied_at) VALUES (?, ?)")
+            .bind(version)
+            .bind(Utc::now())
+            .execute(&mut transaction)
+            .await?;
+        transaction.commit().await?;
+        println!("Applied migration {}", version);
+    }
     Ok(())
 }
 
-pub async fn delete_indexes(db: &SqlitePool) -> anyhow::Result<()> {
-    sqlx::query(DELETE_INDEXES_SQL).execute(db).await?;
+/// Computes `last_name_soundex` for every entity loaded before the column existed
+async fn fill_last_name_soundex(conn: &mut SqliteConnection) -> anyhow::Result<()> {
+    register_soundex(conn).await?;
+    sqlx::query("UPDATE entities SET last_name_soundex = artemis_soundex(last_name)")
+        .execute(&mut *conn)
+        .await?;
     Ok(())
 }
+
+/// Registers [`soundex`] as the SQL function `artemis_soundex(name)` on this connection
+///
+/// SQLite has a `soundex` of its own, but it doesn't skip H and W the same way, so the codes it
+/// computes wouldn't match the ones the loader and search do.
+async fn register_soundex(conn: &mut SqliteConnection) -> anyhow::Result<()> {
+    unsafe extern "C" fn soundex_function(
+        context: *mut ffi::sqlite3_context,
+        _argc: c_int,
+        argv: *mut *mut ffi::sqlite3_value,
+    ) {
+        let value = *argv;
+        let text = ffi::sqlite3_value_text(value);
+        if text.is_null() {
+            ffi::sqlite3_result_null(context);
+            return;
+        }
+        let name = std::slice::from_raw_parts(text, ffi::sqlite3_value_bytes(value) as usize);
+        let code = soundex(&String::from_utf8_lossy(name));
+        ffi::sqlite3_result_text(
+            context,
+            code.as_ptr().cast::<c_char>(),
+            code.len() as c_int,
+            ffi::SQLITE_TRANSIENT(),
+        );
+    }
+
+    let mut handle = conn.lock_handle().await?;
+    // Safe because the handle is locked, so nothing else is using the connection, and the
+    // function doesn't hold on to anything SQLite passes it
+    let result = unsafe {
+        ffi::sqlite3_create_function_v2(
+            handle.as_raw_handle().as_ptr(),
+            c"artemis_soundex".as_ptr(),
+            1,
+            ffi::SQLITE_UTF8 | ffi::SQLITE_DETERMINISTIC,
+            ptr::null_mut(),
+            Some(soundex_function),
+            None,
+            None,
+            None,
+        )
+    };
+    if result != ffi::SQLITE_OK {
+        bail!(
+            "Error registering soundex function: SQLite error {}",
+            result
+        );
+    }
+    Ok(())
+}
+
+/// Recreates the indexes [`delete_indexes`] dropped, and rebuilds `entities_fts` from scratch
+pub async fn create_indexes(conn: &mut SqliteConnection) -> anyhow::Result<()> {
+    sqlx::query(CREATE_INDEXES_SQL).execute(&mut *conn).await?;
+    sqlx::query(REBUILD_ENTITY_SEARCH_SQL)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Drops the indexes before a weekly load, along with the triggers keeping `entities_fts` in
+/// sync, since it's much faster to build them all once at the end with [`create_indexes`]
+pub async fn delete_indexes(conn: &mut SqliteConnection) -> anyhow::Result<()> {
+    sqlx::query(DELETE_INDEXES_SQL).execute(&mut *conn).await?;
+    sqlx::query(SUSPEND_ENTITY_SEARCH_SQL)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Runs SQLite's `PRAGMA integrity_check`, returning an error with its findings if it fails
+pub async fn integrity_check(db: &SqlitePool) -> anyhow::Result<()> {
+    let results: Vec<(String,)> = sqlx::query_as("PRAGMA integrity_check")
+        .fetch_all(db)
+        .await?;
+    if results.len() == 1 && results[0].0 == "ok" {
+        return Ok(());
+    }
+    bail!(
+        "Integrity check failed: {}",
+        results
+            .into_iter()
+            .map(|(result,)| result)
+            .collect::<Vec<_>>()
+            .join("; ")
+    )
+}
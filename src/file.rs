@@ -1,123 +1,361 @@
-use chrono::DateTime;
+use chrono::{DateTime, TimeZone, Utc};
 use filetime::{self, FileTime};
 use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 use regex::Regex;
 
-use std::fs::{self, File};
-use std::io::{Read, Write};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// How many times to try a download before giving up
+const MAX_ATTEMPTS: u32 = 5;
+/// How long to wait after the first failed attempt, doubling after each one after that
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+/// How much of the response body to read at a time
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub enum DownloadError {
+    /// The request couldn't be sent, or the connection failed before a response came back
+    Transport {
+        url: String,
+        source: Box<ureq::Transport>,
+    },
+    /// The server responded with an error status
+    Status { url: String, status: u16 },
+    /// The server sent a range of the file other than the one asked for
+    UnexpectedRange { url: String },
+    /// The connection dropped while reading the response body
+    Read { url: String, source: io::Error },
+    /// The response body ended before the length the server said it would be
+    Incomplete {
+        file: String,
+        expected: u64,
+        actual: u64,
+    },
+    /// The file name couldn't be worked out from the URL
+    FileName(String),
+    /// A local file couldn't be read or written
+    Io { file: String, source: io::Error },
+}
+
+impl DownloadError {
+    /// Whether trying again could help, which is the case for anything that isn't our fault or
+    /// the server saying no
+    fn is_retryable(&self) -> bool {
+        match self {
+            DownloadError::Status { status, .. } => {
+                *status == 416 || *status == 429 || *status >= 500
+            }
+            DownloadError::Transport { .. }
+            | DownloadError::UnexpectedRange { .. }
+            | DownloadError::Read { .. }
+            | DownloadError::Incomplete { .. } => true,
+            DownloadError::FileName(_) | DownloadError::Io { .. } => false,
+        }
+    }
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DownloadError::Transport { url, source } => {
+                write!(f, "Error requesting {url}: {source}")
+            }
+            DownloadError::Status { url, status } => {
+                write!(f, "Error requesting {url}: server responded with {status}")
+            }
+            DownloadError::UnexpectedRange { url } => {
+                write!(f, "Error resuming {url}: server sent the wrong range")
+            }
+            DownloadError::Read { url, source } => write!(f, "Error downloading {url}: {source}"),
+            DownloadError::Incomplete {
+                file,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Error downloading {file}: expected {expected} bytes but got {actual}"
+            ),
+            DownloadError::FileName(url) => write!(f, "Error parsing file name from URL {url}"),
+            DownloadError::Io { file, source } => write!(f, "Error accessing {file}: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for DownloadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DownloadError::Transport { source, .. } => Some(source.as_ref()),
+            DownloadError::Read { source, .. } | DownloadError::Io { source, .. } => Some(source),
+            DownloadError::Status { .. }
+            | DownloadError::UnexpectedRange { .. }
+            | DownloadError::Incomplete { .. }
+            | DownloadError::FileName(_) => None,
+        }
+    }
+}
+
+/// How a file on disk compares to the one on the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    /// There's no file on disk
+    Missing,
+    /// The file on disk is at least as new as the server's, and the same size
+    Current,
+    /// The file on disk is new enough, but the wrong size
+    Incomplete,
+    /// The server's file was modified after the one on disk
+    Outdated,
+}
+
+/// Compares a file on disk against the length and Last-Modified time (in seconds since the epoch)
+/// the server gave for it
+///
+/// Downloads set the file's mtime to the server's Last-Modified, so a file that's been modified
+/// since is still considered current. Anything the server didn't tell us isn't checked.
+///
+/// # Examples
+/// ```
+/// use artemis::file::{validate_cache, CacheStatus};
+/// use filetime::FileTime;
+///
+/// let path = std::env::temp_dir().join("artemis-validate-cache-example");
+/// assert_eq!(validate_cache(&path, Some(5), None).unwrap(), CacheStatus::Missing);
+///
+/// std::fs::write(&path, "hello").unwrap();
+/// filetime::set_file_mtime(&path, FileTime::from_unix_time(1_000_000, 0)).unwrap();
+/// assert_eq!(validate_cache(&path, Some(5), Some(1_000_000)).unwrap(), CacheStatus::Current);
+/// assert_eq!(validate_cache(&path, None, None).unwrap(), CacheStatus::Current);
+/// assert_eq!(validate_cache(&path, Some(10), None).unwrap(), CacheStatus::Incomplete);
+/// assert_eq!(validate_cache(&path, Some(5), Some(2_000_000)).unwrap(), CacheStatus::Outdated);
+/// # std::fs::remove_file(&path).unwrap();
+/// ```
+pub fn validate_cache(
+    path: &Path,
+    len: Option<u64>,
+    last_modified: Option<i64>,
+) -> io::Result<CacheStatus> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(CacheStatus::Missing),
+        Err(err) => return Err(err),
+    };
+    let mtime = FileTime::from_last_modification_time(&metadata);
+
+    if mtime.unix_seconds() < last_modified.unwrap_or(1) {
+        Ok(CacheStatus::Outdated)
+    } else if len.is_some_and(|len| len != metadata.len()) {
+        Ok(CacheStatus::Incomplete)
+    } else {
+        Ok(CacheStatus::Current)
+    }
+}
 
 /// Downloads a file from the given URL to the given path
 ///
+/// The request is conditional on the copy already on disk, if there is one, so an unchanged file
+/// isn't downloaded again. The download goes to a `.part` file next to the output, and if it's
+/// interrupted the next attempt picks up where it left off with a Range request, as long as the
+/// server's file hasn't changed in between. Failed attempts are retried with exponential backoff.
+///
 /// # Arguments
 ///
 /// * `url` - The URL to download from
-/// * `path` - The path to download to. If None, try and use the Content-Disposition
-/// header to determine the filename, and fall back to the last segment of the URL
+/// * `path` - The path to download to. If None, use the last segment of the URL in the current
+///   directory
 ///
 /// # Examples
-/// ```
+/// ```no_run
+/// # use artemis::file::download_file;
 /// download_file("https://data.fcc.gov/download/pub/uls/complete/l_amat.zip", None);
 /// ```
-pub fn download_file(url: &str, file_name: Option<&str>) -> anyhow::Result<File> {
-    let resp = ureq::get(url).call().expect("Error downloading file");
+pub fn download_file(url: &str, path: Option<&Path>) -> Result<File, DownloadError> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => PathBuf::from(parse_file_name_from_url(url)?),
+    };
 
-    // We can work on handling not having a Content-Length header later
-    assert!(resp.has("Content-Length"));
-    let len: u64 = resp
-        .header("Content-Length")
-        .unwrap()
-        .parse()
-        .expect("Error parsing Content-Length header");
+    let mut attempt = 1;
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        match try_download(url, &path) {
+            Err(err) if err.is_retryable() && attempt < MAX_ATTEMPTS => {
+                println!("{}, retrying in {}s", err, backoff.as_secs());
+                thread::sleep(backoff);
+                attempt += 1;
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn parse_file_name_from_url(url: &str) -> Result<String, DownloadError> {
+    let output_file_name_regex = Regex::new(r"/(\w+\.?\w*)").expect("Error constructing regex");
+    output_file_name_regex
+        .captures_iter(url)
+        .last()
+        .and_then(|captures| captures.get(1))
+        .map(|file_name_match| String::from(file_name_match.as_str()))
+        .ok_or_else(|| DownloadError::FileName(url.to_string()))
+}
+
+/// Returns the path with an extra extension tacked on, like `l_amat.zip.part`
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
 
-    let last_modified = match resp.header("Last-Modified") {
-        Some(last_mod) => match DateTime::parse_from_rfc2822(last_mod) {
-            Ok(dt) => Some(dt.timestamp()),
-            Err(_) => None,
+/// Reads a small sidecar file, like the ETag saved next to a download
+fn read_sidecar(path: &Path) -> Result<Option<String>, DownloadError> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents.trim().to_string())),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(io_error(path, source)),
+    }
+}
+
+/// Writes a sidecar file, or removes it if there's nothing to write
+fn write_sidecar(path: &Path, contents: Option<&str>) -> Result<(), DownloadError> {
+    let result = match contents {
+        Some(contents) => fs::write(path, contents),
+        None => match fs::remove_file(path) {
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            result => result,
         },
-        None => None,
     };
+    result.map_err(|source| io_error(path, source))
+}
 
-    // Time to determine the file name!
-    // Start by seeing if we were told anything, that makes it easy
-    // This is just a helper. It should be its own function. lmao.
-    let parse_file_name_from_url = |url: &str| {
-        let output_file_name_regex = Regex::new(r"/(\w+\.?\w*)").expect("Error constructing regex");
-        let Some(file_name_captures) = output_file_name_regex.captures_iter(url).last() else {
-            panic!("Error parsing file name from URL");
-        };
-        let Some(maybe_match) = file_name_captures.iter().last() else {
-            panic!("Error parsing file name from URL");
-        };
-        let Some(file_name_match) = maybe_match else {
-            panic!("Error parsing file name from URL");
-        };
-        String::from(file_name_match.as_str())
+fn io_error(path: &Path, source: io::Error) -> DownloadError {
+    DownloadError::Io {
+        file: path.display().to_string(),
+        source,
+    }
+}
+
+/// Formats a Unix timestamp as an HTTP date, for `If-Modified-Since`
+fn http_date(timestamp: i64) -> Option<String> {
+    Utc.timestamp_opt(timestamp, 0)
+        .single()
+        .map(|date| date.format("%a, %d %b %Y %H:%M:%S GMT").to_string())
+}
+
+/// Makes a single attempt at downloading the file, resuming a partial one if it can
+fn try_download(url: &str, path: &Path) -> Result<File, DownloadError> {
+    // The ETag of the finished file, and the validator (ETag or Last-Modified) of the partial one.
+    // If-Range takes either, so a partial file can be resumed even if the server has no ETags.
+    let etag_path = with_extension(path, "etag");
+    let part_path = with_extension(path, "part");
+    let part_validator_path = with_extension(&part_path, "etag");
+
+    let mut request = ureq::get(url);
+    if let Ok(metadata) = fs::metadata(path) {
+        let mtime = FileTime::from_last_modification_time(&metadata);
+        if let Some(date) = http_date(mtime.unix_seconds()) {
+            request = request.set("If-Modified-Since", &date);
+        }
+        if let Some(etag) = read_sidecar(&etag_path)? {
+            request = request.set("If-None-Match", &etag);
+        }
+    }
+    let resume_from = match (
+        fs::metadata(&part_path),
+        read_sidecar(&part_validator_path)?,
+    ) {
+        (Ok(metadata), Some(validator)) if metadata.len() > 0 => {
+            request = request
+                .set("Range", &format!("bytes={}-", metadata.len()))
+                .set("If-Range", &validator);
+            metadata.len()
+        }
+        _ => 0,
     };
-    let output_file_name = match file_name {
-        Some(n) => String::from(n),
-        None => {
-            // We weren't given a file name by the user, so we need to figure it out ourself
-            match resp.header("Content-Disposition") {
-                // A Content-Disposition header is present, so we can use that
-                Some(content_disposition) => {
-                    let content_disposition_regex =
-                        Regex::new(r#"filename="([\w\.]+)""#).expect("Error compiling regex");
-                    // Check if the Content-Disposition header specifies a filename
-                    match content_disposition_regex.captures(content_disposition) {
-                        Some(cd_match) => {
-                            // We have a filename, so use that
-                            // TODO: Make less unwrappy
-                            cd_match
-                                .iter()
-                                .last()
-                                .unwrap()
-                                .unwrap()
-                                .as_str()
-                                .to_string()
-                        }
-                        None => {
-                            // It doesn't, so we have to fall back to the file name in the URL
-                            parse_file_name_from_url(url)
-                        }
-                    }
-                }
-                // No Content-Disposition header, so we have to fall back to the file name in the URL
-                None => parse_file_name_from_url(url),
+
+    let resp = match request.call() {
+        Ok(resp) => resp,
+        Err(ureq::Error::Status(status, _)) => {
+            if status == 416 {
+                // The partial file is somehow longer than the server's, so start over
+                write_sidecar(&part_validator_path, None)?;
             }
+            return Err(DownloadError::Status {
+                url: url.to_string(),
+                status,
+            });
+        }
+        Err(ureq::Error::Transport(source)) => {
+            return Err(DownloadError::Transport {
+                url: url.to_string(),
+                source: Box::new(source),
+            })
         }
     };
 
-    if std::path::Path::new(&output_file_name).exists() {
-        let file_metadata = fs::metadata(&output_file_name).expect("Error getting file metadata");
-        let mtime = FileTime::from_last_modification_time(&file_metadata);
+    if resp.status() == 304 {
+        println!("File already downloaded");
+        return File::open(path).map_err(|source| io_error(path, source));
+    }
 
-        match (
-            mtime.seconds() >= last_modified.unwrap_or(1),
-            file_metadata.len() == len,
-        ) {
-            (true, true) => {
+    // ureq decodes compressed bodies itself, so Content-Length is only useful if there's no encoding
+    let len: Option<u64> = resp
+        .header("Content-Length")
+        .filter(|_| resp.header("Content-Encoding").is_none())
+        .and_then(|len| len.parse().ok());
+    let last_modified = resp
+        .header("Last-Modified")
+        .and_then(|last_mod| DateTime::parse_from_rfc2822(last_mod).ok())
+        .map(|dt| dt.timestamp());
+    let etag = resp.header("ETag").map(String::from);
+
+    let (mut output_file, offset) = if resp.status() == 206 {
+        let expected_range = format!("bytes {}-", resume_from);
+        if !resp
+            .header("Content-Range")
+            .is_some_and(|range| range.starts_with(&expected_range))
+        {
+            write_sidecar(&part_validator_path, None)?;
+            return Err(DownloadError::UnexpectedRange {
+                url: url.to_string(),
+            });
+        }
+        println!("Resuming download from {} bytes", resume_from);
+        let file = OpenOptions::new()
+            .append(true)
+            .open(&part_path)
+            .map_err(|source| io_error(&part_path, source))?;
+        (file, resume_from)
+    } else {
+        // Some servers ignore conditional requests, so check the file the old fashioned way too
+        match validate_cache(path, len, last_modified).map_err(|source| io_error(path, source))? {
+            CacheStatus::Current => {
                 println!("File already downloaded");
-                return Ok(File::open(output_file_name).expect("Error opening file"));
-            }
-            (true, false) => {
-                println!("File already downloaded, but is incomplete");
-            }
-            (false, _) => {
-                println!("File already downloaded, but is out of date");
+                return File::open(path).map_err(|source| io_error(path, source));
             }
+            CacheStatus::Incomplete => println!("File already downloaded, but is incomplete"),
+            CacheStatus::Outdated => println!("File already downloaded, but is out of date"),
+            CacheStatus::Missing => println!("File does not exist, downloading"),
         }
-    } else {
-        println!("File does not exist, downloading");
-    }
-
-    let mut output_file = fs::File::create(&output_file_name).expect("Error creating output file");
 
-    let mut reader = resp.into_reader();
-    let chunk_size = len / 99;
+        let validator = etag.as_deref().or_else(|| resp.header("Last-Modified"));
+        write_sidecar(&part_validator_path, validator)?;
+        let file = File::create(&part_path).map_err(|source| io_error(&part_path, source))?;
+        (file, 0)
+    };
+    let total = len.map(|len| len + offset);
 
-    let progress_bar = ProgressBar::new(len);
+    let progress_bar = match total {
+        Some(total) => ProgressBar::new(total),
+        None => ProgressBar::new_spinner(),
+    };
     progress_bar.set_draw_target(ProgressDrawTarget::stderr_with_hz(1));
-    progress_bar.set_message(output_file_name.clone());
+    progress_bar.set_message(path.display().to_string());
     progress_bar.set_style(
         ProgressStyle::with_template(
             "[{elapsed}+{eta}/{duration}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}) {msg}",
@@ -125,79 +363,51 @@ pub fn download_file(url: &str, file_name: Option<&str>) -> anyhow::Result<File>
         .unwrap()
         .progress_chars("#>-"),
     );
+    progress_bar.set_position(offset);
 
+    let mut reader = resp.into_reader();
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+    let mut written = offset;
     loop {
-        let mut chunk = vec![0u8; chunk_size as usize];
-        let bytes_read = reader.read(&mut chunk[..]).expect("Error reading chunk");
-        chunk.truncate(bytes_read); // This way we don't end with a ton of leading 0s
-        if bytes_read > 0 {
-            output_file
-                .write_all(chunk.as_slice())
-                .expect("Error writing to output file");
-
-            progress_bar.inc(bytes_read as u64);
-        } else {
-            break;
-        }
-    }
-
-    output_file.flush().expect("Error flushing output file");
-    if let Some(modified) = last_modified {
-        filetime::set_file_mtime(&output_file_name, FileTime::from_unix_time(modified, 0))
-            .expect("Error setting file mtime");
+        let bytes_read = match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(bytes_read) => bytes_read,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(source) => {
+                return Err(DownloadError::Read {
+                    url: url.to_string(),
+                    source,
+                })
+            }
+        };
+        output_file
+            .write_all(&chunk[..bytes_read])
+            .map_err(|source| io_error(&part_path, source))?;
+        written += bytes_read as u64;
+        progress_bar.set_position(written);
     }
+    output_file
+        .flush()
+        .map_err(|source| io_error(&part_path, source))?;
+    drop(output_file);
     progress_bar.finish();
 
-    Ok(fs::File::open(&output_file_name).expect("Error opening output file"))
-}
-
-pub fn unzip_file(zip_file: &File) -> anyhow::Result<()> {
-    let mut archive = zip::ZipArchive::new(zip_file).expect("Error opening zip archive");
-
-    let progress_bar = ProgressBar::new(archive.len().try_into().unwrap());
-    progress_bar.set_message("");
-    progress_bar.set_style(
-        ProgressStyle::with_template(
-            "[{elapsed}+{eta}/{duration}] [{bar:40.cyan/blue}] {human_pos}/{human_len} {msg}",
-        )
-        .unwrap()
-        .progress_chars("#>-"),
-    );
-
-    for i in 0..archive.len() {
-        let mut file = archive
-            .by_index(i)
-            .expect("Error getting file from archive");
-        let unzip_path = match file.enclosed_name() {
-            Some(path) => path.to_owned(),
-            None => continue,
-        };
-        progress_bar.set_message(format!("{}", unzip_path.display()));
-
-        if (*file.name()).ends_with('/') {
-            fs::create_dir_all(&unzip_path).expect("Error creating directory");
-        } else {
-            if let Some(p) = unzip_path.parent() {
-                if !p.exists() {
-                    fs::create_dir_all(p).expect("Error creating directory");
-                }
-            }
-            let mut unzip_file = fs::File::create(&unzip_path).expect("Error creating file");
-            std::io::copy(&mut file, &mut unzip_file).expect("Error copying file");
-        }
-
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
+    if let Some(expected) = total.filter(|total| *total != written) {
+        // Leave the partial file around so the next attempt can resume it
+        return Err(DownloadError::Incomplete {
+            file: part_path.display().to_string(),
+            expected,
+            actual: written,
+        });
+    }
 
-            if let Some(mode) = file.unix_mode() {
-                fs::set_permissions(&unzip_path, fs::Permissions::from_mode(mode)).unwrap();
-            }
-        }
-        // TODO: Also set and check file mtime
-        progress_bar.set_position((i + 1).try_into().unwrap());
+    fs::rename(&part_path, path).map_err(|source| io_error(path, source))?;
+    write_sidecar(&part_validator_path, None)?;
+    write_sidecar(&etag_path, etag.as_deref())?;
+    if let Some(modified) = last_modified {
+        filetime::set_file_mtime(path, FileTime::from_unix_time(modified, 0))
+            .map_err(|source| io_error(path, source))?;
     }
 
-    progress_bar.finish();
-    Ok(())
+    File::open(path).map_err(|source| io_error(path, source))
 }
@@ -0,0 +1,150 @@
+//! Decoding for the event codes in `HS.dat`
+//!
+//! The FCC doesn't publish a list of these anywhere obvious, so this only covers the codes that
+//! show up on amateur licenses. Anything else is passed through as [`EventKind::Other`].
+
+use chrono::NaiveDate;
+use serde::Serialize;
+use sqlx::{FromRow, SqlitePool};
+
+/// What kind of thing happened to a license
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Issued,
+    Renewed,
+    Modified,
+    Expired,
+    Cancelled,
+    Reinstated,
+    Assigned,
+    Purged,
+    Other,
+}
+
+/// A single known history code
+#[derive(Debug, PartialEq, Eq)]
+pub struct HistoryCode {
+    pub code: &'static str,
+    pub kind: EventKind,
+    pub description: &'static str,
+}
+
+const HISTORY_CODES: &[HistoryCode] = &[
+    HistoryCode {
+        code: "LIISS",
+        kind: EventKind::Issued,
+        description: "License Issued",
+    },
+    HistoryCode {
+        code: "LIREN",
+        kind: EventKind::Renewed,
+        description: "License Renewed",
+    },
+    HistoryCode {
+        code: "LIMOD",
+        kind: EventKind::Modified,
+        description: "License Modified",
+    },
+    HistoryCode {
+        code: "LIEXP",
+        kind: EventKind::Expired,
+        description: "License Expired",
+    },
+    HistoryCode {
+        code: "LICAN",
+        kind: EventKind::Cancelled,
+        description: "License Cancelled",
+    },
+    HistoryCode {
+        code: "LIREI",
+        kind: EventKind::Reinstated,
+        description: "License Reinstated",
+    },
+    HistoryCode {
+        code: "LIASG",
+        kind: EventKind::Assigned,
+        description: "License Assigned",
+    },
+    HistoryCode {
+        code: "LITRA",
+        kind: EventKind::Assigned,
+        description: "License Transferred",
+    },
+    HistoryCode {
+        code: "LIPUR",
+        kind: EventKind::Purged,
+        description: "License Purged",
+    },
+];
+
+/// Looks up a history code, returning `None` for ones we don't know about
+///
+/// # Examples
+/// ```
+/// use artemis::history::{lookup, EventKind};
+///
+/// let code = lookup("LIREN").unwrap();
+/// assert_eq!(code.kind, EventKind::Renewed);
+/// assert_eq!(code.description, "License Renewed");
+///
+/// assert!(lookup("NOPE").is_none());
+/// ```
+pub fn lookup(code: &str) -> Option<&'static HistoryCode> {
+    HISTORY_CODES.iter().find(|known| known.code == code.trim())
+}
+
+/// A single decoded entry in a call sign's history
+#[derive(Debug, Serialize)]
+pub struct TimelineEvent {
+    pub date: Option<NaiveDate>,
+    pub unique_system_identifier: u32,
+    pub call_sign: String,
+    pub code: String,
+    pub kind: EventKind,
+    /// The code's description, or `None` if it isn't one we know
+    pub description: Option<&'static str>,
+}
+
+#[derive(FromRow)]
+struct HistoryRow {
+    unique_system_identifier: u32,
+    call_sign: String,
+    log_date: Option<NaiveDate>,
+    code: String,
+}
+
+/// Returns the history of every license that has held a call sign, oldest first
+pub async fn get_timeline(
+    db: &SqlitePool,
+    call_sign: &str,
+) -> Result<Vec<TimelineEvent>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, HistoryRow>(
+        "SELECT unique_system_identifier, call_sign, log_date, code
+        FROM history
+        WHERE unique_system_identifier IN (
+            SELECT unique_system_identifier FROM headers WHERE call_sign = ?1
+            UNION
+            SELECT unique_system_identifier FROM history WHERE call_sign = ?1
+        )
+        ORDER BY log_date, unique_system_identifier",
+    )
+    .bind(call_sign)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let known = lookup(&row.code);
+            TimelineEvent {
+                date: row.log_date,
+                unique_system_identifier: row.unique_system_identifier,
+                call_sign: row.call_sign,
+                kind: known.map_or(EventKind::Other, |known| known.kind),
+                description: known.map(|known| known.description),
+                code: row.code,
+            }
+        })
+        .collect())
+}
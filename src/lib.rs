@@ -1,8 +1,19 @@
+pub mod callsign;
+pub mod codes;
+pub mod config;
 pub mod db;
 pub mod fcc_date;
 pub mod file;
+pub mod history;
+pub mod license;
 pub mod load;
 pub mod meta;
+pub mod records;
+pub mod search;
+pub mod sequential;
+pub mod source;
+pub mod special_condition_codes;
 pub mod types;
+pub mod vanity;
 
 pub use types::*;
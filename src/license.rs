@@ -0,0 +1,467 @@
+use chrono::NaiveDate;
+use itertools::Itertools;
+use serde::Serialize;
+use sqlx::{FromRow, QueryBuilder, Sqlite, SqlitePool};
+use std::collections::HashMap;
+
+use crate::codes::{ApplicantType, EntityType, LicenseStatus, OperatorClass};
+use crate::load::BIND_LIMIT;
+use crate::special_condition_codes::SpecialConditionCode;
+
+/// Everything the dumps have on a single license, keyed by its unique system identifier
+#[derive(Debug, Serialize)]
+pub struct License {
+    pub unique_system_identifier: u32,
+    pub header: LicenseHeader,
+    pub amateur: Option<LicenseAmateur>,
+    pub entity: Option<LicenseEntity>,
+    pub comments: Vec<LicenseComment>,
+    pub history: Vec<LicenseHistory>,
+    pub attachments: Vec<LicenseAttachmentEntry>,
+    pub conditions: Conditions,
+}
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct LicenseHeader {
+    pub uls_file_number: String,
+    pub call_sign: String,
+    pub license_status: LicenseStatus,
+    pub radio_service_code: String,
+    pub grant_date: Option<NaiveDate>,
+    pub expired_date: Option<NaiveDate>,
+    pub cancellation_date: Option<NaiveDate>,
+    pub effective_date: Option<NaiveDate>,
+    pub last_action_date: Option<NaiveDate>,
+    pub eligibility_rule_number: String,
+    pub revoked: String,
+    pub convicted: String,
+    pub adjudged: String,
+    pub licensee_name_change: String,
+}
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct LicenseAmateur {
+    pub call_sign: String,
+    pub operator_class: Option<OperatorClass>,
+    pub group_code: String,
+    pub region_code: Option<i64>,
+    pub trustee_call_sign: String,
+    pub trustee_indicator: String,
+    pub trustee_name: String,
+    pub systematic_call_sign_change: String,
+    pub vanity_call_sign_change: String,
+    pub vanity_relationship: String,
+    pub previous_call_sign: String,
+    pub previous_operator_class: Option<OperatorClass>,
+}
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct LicenseEntity {
+    pub entity_type: EntityType,
+    pub licensee_id: String,
+    pub frn: String,
+    pub entity_name: String,
+    pub first_name: String,
+    pub mi: String,
+    pub last_name: String,
+    pub suffix: String,
+    pub street_address: String,
+    pub po_box: String,
+    pub attention_line: String,
+    pub city: String,
+    pub state: String,
+    pub zip_code: String,
+    pub applicant_type_code: Option<ApplicantType>,
+    pub status_code: String,
+    pub status_date: Option<NaiveDate>,
+}
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct LicenseComment {
+    pub comment_date: Option<NaiveDate>,
+    pub description: String,
+    pub status_code: String,
+    pub status_date: Option<NaiveDate>,
+}
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct LicenseHistory {
+    pub log_date: Option<NaiveDate>,
+    pub code: String,
+}
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct LicenseAttachmentEntry {
+    pub attachment_code: String,
+    pub attachment_description: String,
+    pub attachment_date: Option<NaiveDate>,
+    pub attachment_file_name: String,
+    pub action_performed: String,
+}
+
+/// The basics of the license holding a call sign, for looking up lots of calls at once
+#[derive(Debug, Clone, FromRow, Serialize)]
+pub struct LicenseSummary {
+    pub call_sign: String,
+    pub unique_system_identifier: u32,
+    pub license_status: LicenseStatus,
+    pub operator_class: Option<OperatorClass>,
+    pub entity_name: Option<String>,
+    pub first_name: Option<String>,
+    pub mi: Option<String>,
+    pub last_name: Option<String>,
+    pub city: Option<String>,
+    pub state: Option<String>,
+    pub frn: Option<String>,
+    pub grant_date: Option<NaiveDate>,
+    pub expired_date: Option<NaiveDate>,
+}
+
+/// Everything restricting a single license, from both the coded and free-form conditions
+#[derive(Debug, Serialize)]
+pub struct Conditions {
+    pub special_conditions: Vec<SpecialConditionText>,
+    pub free_form_conditions: Vec<FreeFormCondition>,
+}
+
+/// A coded special condition with its text from `special_condition_codes`
+#[derive(Debug, Serialize)]
+pub struct SpecialConditionText {
+    /// The condition's code, or `None` if the FCC left it blank
+    pub code: Option<i32>,
+    pub condition_type: Option<String>,
+    pub status_code: Option<String>,
+    pub status_date: Option<NaiveDate>,
+    /// The condition's text, or `None` if the code is blank or isn't in `special_condition_codes`
+    pub description: Option<String>,
+}
+
+/// A free-form condition, with every sequenced part joined back together
+#[derive(Debug, Serialize)]
+pub struct FreeFormCondition {
+    pub identifier: String,
+    pub condition_type: Option<String>,
+    pub status_code: Option<String>,
+    pub status_date: Option<NaiveDate>,
+    pub text: String,
+}
+
+#[derive(FromRow)]
+struct SpecialConditionRow {
+    special_conditions_code: Option<i32>,
+    special_conditions_type: Option<String>,
+    status_code: Option<String>,
+    status_date: Option<NaiveDate>,
+    code: Option<i32>,
+    service: Option<String>,
+    text_1: Option<String>,
+    text_2: Option<String>,
+    text_3: Option<String>,
+    text_4: Option<String>,
+    text_5: Option<String>,
+    flag: Option<String>,
+}
+
+#[derive(FromRow)]
+struct FreeFormRow {
+    unique_license_free_form_identifier: String,
+    license_free_form_type: Option<String>,
+    status_code: Option<String>,
+    status_date: Option<NaiveDate>,
+    license_free_form_condition: Option<String>,
+}
+
+/// Returns the full license with the given unique system identifier, or `None` if there isn't one
+pub async fn get_license(
+    db: &SqlitePool,
+    unique_system_identifier: u32,
+) -> Result<Option<License>, sqlx::Error> {
+    let header = sqlx::query_as::<_, LicenseHeader>(
+        "SELECT
+            uls_file_number,
+            call_sign,
+            license_status,
+            radio_service_code,
+            grant_date,
+            expired_date,
+            cancellation_date,
+            effective_date,
+            last_action_date,
+            eligibility_rule_number,
+            revoked,
+            convicted,
+            adjudged,
+            licensee_name_change
+        FROM headers
+        WHERE unique_system_identifier = ?",
+    )
+    .bind(unique_system_identifier)
+    .fetch_optional(db)
+    .await?;
+    let header = match header {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+
+    // The region is stored from the raw dump, so it's an empty string rather than NULL if unset
+    let amateur = sqlx::query_as::<_, LicenseAmateur>(
+        "SELECT
+            call_sign,
+            operator_class,
+            group_code,
+            NULLIF(region_code, '') AS region_code,
+            trustee_call_sign,
+            trustee_indicator,
+            trustee_name,
+            systematic_call_sign_change,
+            vanity_call_sign_change,
+            vanity_relationship,
+            previous_call_sign,
+            previous_operator_class
+        FROM amateurs
+        WHERE unique_system_identifier = ?",
+    )
+    .bind(unique_system_identifier)
+    .fetch_optional(db)
+    .await?;
+
+    let entity = sqlx::query_as::<_, LicenseEntity>(
+        "SELECT
+            entity_type,
+            licensee_id,
+            frn,
+            entity_name,
+            first_name,
+            mi,
+            last_name,
+            suffix,
+            street_address,
+            po_box,
+            attention_line,
+            city,
+            state,
+            zip_code,
+            applicant_type_code,
+            status_code,
+            status_date
+        FROM entities
+        WHERE unique_system_identifier = ?",
+    )
+    .bind(unique_system_identifier)
+    .fetch_optional(db)
+    .await?;
+
+    let comments = sqlx::query_as::<_, LicenseComment>(
+        "SELECT comment_date, description, status_code, status_date
+        FROM comments
+        WHERE unique_system_identifier = ?
+        ORDER BY comment_date",
+    )
+    .bind(unique_system_identifier)
+    .fetch_all(db)
+    .await?;
+
+    let history = sqlx::query_as::<_, LicenseHistory>(
+        "SELECT log_date, code
+        FROM history
+        WHERE unique_system_identifier = ?
+        ORDER BY log_date",
+    )
+    .bind(unique_system_identifier)
+    .fetch_all(db)
+    .await?;
+
+    let attachments = sqlx::query_as::<_, LicenseAttachmentEntry>(
+        "SELECT
+            attachment_code,
+            attachment_description,
+            attachment_date,
+            attachment_file_name,
+            action_performed
+        FROM license_attachments
+        WHERE unique_system_identifier = ?
+        ORDER BY attachment_date",
+    )
+    .bind(unique_system_identifier)
+    .fetch_all(db)
+    .await?;
+
+    let conditions = get_conditions(db, unique_system_identifier).await?;
+
+    Ok(Some(License {
+        unique_system_identifier,
+        header,
+        amateur,
+        entity,
+        comments,
+        history,
+        attachments,
+        conditions,
+    }))
+}
+
+/// Returns a summary of the license for each call sign, batching the lookups under the bind
+/// parameter limit
+///
+/// When more than one license has held a call, the active one wins, and otherwise the one granted
+/// most recently. Calls no license has held are left out.
+pub async fn get_summaries(
+    db: &SqlitePool,
+    call_signs: &[String],
+) -> Result<HashMap<String, LicenseSummary>, sqlx::Error> {
+    let mut summaries: HashMap<String, LicenseSummary> = HashMap::new();
+    for chunk in call_signs.chunks(BIND_LIMIT) {
+        let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(
+            "SELECT
+                headers.call_sign,
+                headers.unique_system_identifier,
+                headers.license_status,
+                amateurs.operator_class,
+                entities.entity_name,
+                entities.first_name,
+                entities.mi,
+                entities.last_name,
+                entities.city,
+                entities.state,
+                entities.frn,
+                headers.grant_date,
+                headers.expired_date
+            FROM headers
+            LEFT JOIN amateurs
+                ON amateurs.unique_system_identifier = headers.unique_system_identifier
+            LEFT JOIN entities
+                ON entities.unique_system_identifier = headers.unique_system_identifier
+            WHERE headers.call_sign IN (",
+        );
+        let mut separated = query_builder.separated(", ");
+        for call_sign in chunk {
+            separated.push_bind(call_sign);
+        }
+        query_builder.push(")");
+
+        for summary in query_builder
+            .build_query_as::<LicenseSummary>()
+            .fetch_all(db)
+            .await?
+        {
+            let rank = |summary: &LicenseSummary| {
+                (
+                    summary.license_status == LicenseStatus::Active,
+                    summary.grant_date,
+                )
+            };
+            match summaries.get(&summary.call_sign) {
+                Some(existing) if rank(existing) >= rank(&summary) => {}
+                _ => {
+                    summaries.insert(summary.call_sign.clone(), summary);
+                }
+            }
+        }
+    }
+    Ok(summaries)
+}
+
+/// Returns true if there's a license with the given unique system identifier
+pub async fn exists(db: &SqlitePool, unique_system_identifier: u32) -> Result<bool, sqlx::Error> {
+    let (exists,): (bool,) =
+        sqlx::query_as("SELECT EXISTS(SELECT 1 FROM headers WHERE unique_system_identifier = ?)")
+            .bind(unique_system_identifier)
+            .fetch_one(db)
+            .await?;
+    Ok(exists)
+}
+
+/// Returns the coded and free-form special conditions on a license
+pub async fn get_conditions(
+    db: &SqlitePool,
+    unique_system_identifier: u32,
+) -> Result<Conditions, sqlx::Error> {
+    let special_conditions = sqlx::query_as::<_, SpecialConditionRow>(
+        "SELECT
+            special_conditions.special_conditions_code,
+            special_conditions.special_conditions_type,
+            special_conditions.status_code,
+            special_conditions.status_date,
+            special_condition_codes.code,
+            special_condition_codes.service,
+            special_condition_codes.text_1,
+            special_condition_codes.text_2,
+            special_condition_codes.text_3,
+            special_condition_codes.text_4,
+            special_condition_codes.text_5,
+            special_condition_codes.flag
+        FROM special_conditions
+        -- A blank code is NULL, which doesn't match any code text and is left with NULLs here
+        LEFT JOIN special_condition_codes
+            ON special_condition_codes.code = special_conditions.special_conditions_code
+        WHERE special_conditions.unique_system_identifier = ?
+        ORDER BY
+            special_conditions.special_conditions_code IS NULL,
+            special_conditions.special_conditions_code",
+    )
+    .bind(unique_system_identifier)
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(|row| SpecialConditionText {
+        description: row.code.map(|code| {
+            SpecialConditionCode {
+                code,
+                service: row.service.unwrap_or_default(),
+                text: [
+                    row.text_1.unwrap_or_default(),
+                    row.text_2.unwrap_or_default(),
+                    row.text_3.unwrap_or_default(),
+                    row.text_4.unwrap_or_default(),
+                    row.text_5.unwrap_or_default(),
+                ],
+                flag: row.flag,
+            }
+            .description()
+        }),
+        code: row.special_conditions_code,
+        condition_type: row.special_conditions_type,
+        status_code: row.status_code,
+        status_date: row.status_date,
+    })
+    .collect();
+
+    // Long conditions are split into several rows, which have to be put back together in order
+    let free_form_conditions = sqlx::query_as::<_, FreeFormRow>(
+        "SELECT
+            unique_license_free_form_identifier,
+            license_free_form_type,
+            status_code,
+            status_date,
+            license_free_form_condition
+        FROM special_conditions_free_form
+        WHERE unique_system_identifier = ?
+        ORDER BY unique_license_free_form_identifier, sequence_number",
+    )
+    .bind(unique_system_identifier)
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .group_by(|row| row.unique_license_free_form_identifier.clone())
+    .into_iter()
+    .map(|(identifier, rows)| {
+        let rows = rows.collect::<Vec<_>>();
+        let first = &rows[0];
+        FreeFormCondition {
+            condition_type: first.license_free_form_type.clone(),
+            status_code: first.status_code.clone(),
+            status_date: first.status_date,
+            text: rows
+                .iter()
+                .filter_map(|row| row.license_free_form_condition.as_deref())
+                .collect(),
+            identifier,
+        }
+    })
+    .collect();
+
+    Ok(Conditions {
+        special_conditions,
+        free_form_conditions,
+    })
+}
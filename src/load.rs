@@ -1,655 +1,447 @@
-use crate::types::*;
-use csv::StringRecord;
+use crate::records::RecordReader;
+use crate::special_condition_codes;
+use crate::types::RECORD_TABLES;
+use csv::{DeserializeError, StringRecord};
 use indicatif::{ProgressBar, ProgressStyle};
 use itertools::Itertools;
-use sqlx::{QueryBuilder, Sqlite, SqlitePool};
-use std::fs::File;
-use std::io::BufRead;
-use regex::Regex;
+use serde::de::Error as _;
+use serde::Deserialize;
+use sqlx::query_builder::Separated;
+use sqlx::{QueryBuilder, Sqlite, SqliteConnection};
+use std::collections::HashSet;
+use std::fmt;
 use std::fs;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek};
+use std::path::Path;
+use zip::result::ZipError;
+use zip::ZipArchive;
 
-const INSERT_AMATEUR_SQL: &str = include_str!("sql/insert-amateur.sql");
-const INSERT_COMMENT_SQL: &str = include_str!("sql/insert-comment.sql");
-const INSERT_ENTITY_SQL: &str = include_str!("sql/insert-entity.sql");
-const INSERT_HEADER_SQL: &str = include_str!("sql/insert-header.sql");
-const INSERT_HISTORY_SQL: &str = include_str!("sql/insert-history.sql");
-const INSERT_LICENSE_ATTACHMENT_SQL: &str = include_str!("sql/insert-license-attachment.sql");
-const INSERT_SPECIAL_CONDITION_SQL: &str = include_str!("sql/insert-special-condition.sql");
-const INSERT_SPECIAL_CONDITION_FREE_FORM_SQL: &str =
-    include_str!("sql/insert-special-condition-free-form.sql");
 const INSERT_SPECIAL_CONDITION_CODE_SQL: &str =
     include_str!("sql/insert-special-condition-code.sql");
+const INSERT_REJECTED_RECORD_SQL: &str = include_str!("sql/insert-rejected-record.sql");
+
+pub(crate) const BIND_LIMIT: usize = 32766;
+
+/// Everything that can go wrong while loading a file into the database
+#[derive(Debug)]
+pub enum LoadError {
+    /// The file couldn't be read or written
+    Io {
+        file: String,
+        source: std::io::Error,
+    },
+    /// The file couldn't be found in or read from the dump's zip archive
+    Zip {
+        file: String,
+        source: zip::result::ZipError,
+    },
+    /// A record couldn't be deserialized into its struct
+    Deserialize {
+        file: String,
+        line: u64,
+        source: csv::DeserializeError,
+    },
+    /// A query against the database failed
+    Sql(sqlx::Error),
+}
 
-const BIND_LIMIT: usize = 32766;
-
-pub async fn load_amateurs(db: &SqlitePool, clear_first: bool) {
-    let amateurs_file = File::open("AM.dat");
-    if amateurs_file.is_err() {
-        println!("AM.dat not found, skipping");
-        return;
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io { file, source } => write!(f, "Error accessing {file}: {source}"),
+            LoadError::Zip { file, source } => write!(f, "Error extracting {file}: {source}"),
+            LoadError::Deserialize { file, line, source } => {
+                write!(f, "Error deserializing {file} line {line}: {source}")
+            }
+            LoadError::Sql(source) => write!(f, "Error executing query: {source}"),
+        }
     }
-    let amateurs_file = amateurs_file.unwrap();
-    //let amateurs_file_meta = fs::metadata("AM.dat").expect("Error getting file metadata");
-    let line_count = std::io::BufReader::new(&amateurs_file).lines().count();
-    drop(amateurs_file);
-
-    let amateurs_file = File::open("AM.dat").expect("Error opening file");
-    let mut transaction = db.begin().await.expect("Error starting transaction");
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(false)
-        .delimiter(b'|')
-        .quoting(false)
-        .from_reader(amateurs_file);
-
-    let progress_bar = ProgressBar::new(line_count.try_into().unwrap());
-    progress_bar.set_style(
-        ProgressStyle::with_template(
-            "[{elapsed}+{eta}/{duration}] [{bar:40.cyan/blue}] {human_pos}/{human_len} ({per_sec}) {msg}",
-        )
-        .unwrap()
-        .progress_chars("#>-"),
-    );
-    progress_bar.set_message("AM.dat");
+}
 
-    if clear_first {
-        QueryBuilder::new("DELETE FROM amateurs")
-            .build()
-            .execute(&mut transaction)
-            .await
-            .expect("Error deleting amateurs");
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io { source, .. } => Some(source),
+            LoadError::Zip { source, .. } => Some(source),
+            LoadError::Deserialize { source, .. } => Some(source),
+            LoadError::Sql(source) => Some(source),
+        }
     }
-
-    let chunk_size = BIND_LIMIT / 18;
-    for chunk in &reader.records().chunks(chunk_size) {
-        let chunk = chunk.collect::<Result<Vec<StringRecord>, _>>().unwrap();
-        let chunk = chunk.iter();
-
-        let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(INSERT_AMATEUR_SQL);
-
-        query_builder.push_values(chunk, |mut builder, entry| {
-            let amateur: Amateur = entry.deserialize(None).expect("Error deserializing entry");
-            builder
-                .push_bind(amateur.RecordType)
-                .push_bind(amateur.UniqueSystemIdentifier)
-                .push_bind(amateur.UlsFileNumber)
-                .push_bind(amateur.EBFNumber)
-                .push_bind(amateur.CallSign)
-                .push_bind(amateur.OperatorClass)
-                .push_bind(amateur.GroupCode)
-                .push_bind(amateur.RegionCode)
-                .push_bind(amateur.TrusteeCallSign)
-                .push_bind(amateur.TrusteeIndicator)
-                .push_bind(amateur.PhysicianCertification)
-                .push_bind(amateur.VESignature)
-                .push_bind(amateur.SystematicCallSignChange)
-                .push_bind(amateur.VanityCallSignChange)
-                .push_bind(amateur.VanityRelationship)
-                .push_bind(amateur.PreviousCallSign)
-                .push_bind(amateur.PreviousOperatorClass)
-                .push_bind(amateur.TrusteeName);
-        });
-
-        query_builder
-            .build()
-            .execute(&mut transaction)
-            .await
-            .expect("Error executing query");
-        progress_bar.set_position(progress_bar.position() + chunk_size as u64);
-    }
-
-    transaction
-        .commit()
-        .await
-        .expect("Error committing transaction");
-    std::fs::remove_file("AM.dat").expect("Error deleting AM.dat");
-    progress_bar.finish();
 }
 
-pub async fn load_comments(db: &SqlitePool, clear_first: bool) {
-    let comments_file = File::open("CO.dat");
-    if comments_file.is_err() {
-        println!("CO.dat not found, skipping");
-        return;
+impl From<sqlx::Error> for LoadError {
+    fn from(err: sqlx::Error) -> Self {
+        LoadError::Sql(err)
     }
+}
 
-    // Some idiot at the FCC decided that unescaped newlines in the middle of a field were cool
-    // Uncle Ted may have had some good ideas after all
-    let comments_regex = Regex::new(r"\s*\r\r\n").unwrap();
-    let comments = fs::read_to_string("CO.dat").expect("Error reading file");
-    fs::write(
-        "CO.dat",
-        comments_regex.replace_all(&comments, " ").to_string(),
-    )
-    .expect("Error writing file");
-
-    let comments_file = File::open("CO.dat").unwrap();
-    // let comments_file_meta = fs::metadata("CO.dat").expect("Error getting file metadata");
-    let line_count = std::io::BufReader::new(&comments_file).lines().count();
-    drop(comments_file);
-
-    let comments_file = File::open("CO.dat").expect("Error opening file");
-    let mut transaction = db.begin().await.expect("Error starting transaction");
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(false)
-        .delimiter(b'|')
-        .quoting(false)
-        .from_reader(comments_file);
-
-    let progress_bar = ProgressBar::new(line_count.try_into().unwrap());
-    progress_bar.set_style(
-        ProgressStyle::with_template(
-            "[{elapsed}+{eta}/{duration}] [{bar:40.cyan/blue}] {human_pos}/{human_len} ({per_sec}) {msg}",
-        )
-        .unwrap()
-        .progress_chars("#>-"),
-    );
-    progress_bar.set_message("CO.dat");
-
-    if clear_first {
-        QueryBuilder::new("DELETE FROM comments")
-            .build()
-            .execute(&mut transaction)
-            .await
-            .expect("Error deleting comments");
+impl LoadError {
+    fn io(file: &str) -> impl FnOnce(std::io::Error) -> Self + '_ {
+        move |source| LoadError::Io {
+            file: file.to_string(),
+            source,
+        }
     }
+}
 
-    let chunk_size = BIND_LIMIT / 8;
-    for chunk in &reader.records().chunks(chunk_size) {
-        let chunk = chunk.collect::<Result<Vec<StringRecord>, _>>().unwrap();
-        let chunk = chunk.iter();
-
-        let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(INSERT_COMMENT_SQL);
-
-        query_builder.push_values(chunk, |mut builder, entry| {
-            let comment: Comment = entry.deserialize(None).expect("Error deserializing entry");
-            builder
-                .push_bind(comment.RecordType)
-                .push_bind(comment.UniqueSystemIdentifier)
-                .push_bind(comment.UlsFileNumber)
-                .push_bind(comment.CallSign)
-                .push_bind(comment.CommentDate)
-                .push_bind(comment.Description)
-                .push_bind(comment.StatusCode)
-                .push_bind(comment.StatusDate);
-        });
-
-        query_builder
-            .build()
-            .execute(&mut transaction)
-            .await
-            .expect("Error executing query");
-        progress_bar.set_position(progress_bar.position() + chunk_size as u64);
-    }
+/// A summary of what a single call to a loader did
+#[derive(Debug, Default)]
+pub struct LoadStats {
+    /// The number of records inserted into the table
+    pub records: u64,
+    /// The number of lines written to `rejected_records` instead
+    pub rejected: u64,
+}
 
-    transaction
-        .commit()
-        .await
-        .expect("Error committing transaction");
-    std::fs::remove_file("CO.dat").expect("Error deleting CO.dat");
-    progress_bar.finish();
+/// A record type from one of the pipe-separated `.dat` files in a ULS dump
+///
+/// Implementing this for a struct in `types.rs` is all that's needed for
+/// [`load_table`] to be able to load it.
+pub trait UlsRecord {
+    /// The name of the file in the dump, such as `AM.dat`
+    const FILE_NAME: &'static str;
+    /// The table the records are loaded into
+    const TABLE: &'static str;
+    /// The `INSERT INTO table (columns...)` statement, without the `VALUES` list
+    const INSERT_SQL: &'static str;
+    /// The number of values [`UlsRecord::bind`] pushes for each record
+    const COLUMNS: usize;
+    /// The number of fields in each record in the file, used to put back together records the
+    /// FCC has split across lines
+    const FIELD_COUNT: usize;
+
+    /// The record as deserialized from a single line of the file
+    type Record<'a>: Deserialize<'a>;
+
+    /// Pushes the values of a record, in the same order as the columns in [`UlsRecord::INSERT_SQL`]
+    fn bind<'a>(record: Self::Record<'a>, builder: Separated<'_, 'a, Sqlite, &'static str>);
 }
 
-pub async fn load_entities(db: &SqlitePool, clear_first: bool) {
-    let entities_file = File::open("EN.dat");
-    if entities_file.is_err() {
-        println!("EN.dat not found, skipping");
-        return;
-    }
-    let entities_file = entities_file.unwrap();
-    //let entities_file_meta = fs::metadata("EN.dat").expect("Error getting file metadata");
-    let line_count = std::io::BufReader::new(&entities_file).lines().count();
-    drop(entities_file);
-
-    let entities_file = File::open("EN.dat").expect("Error opening file");
-    let mut transaction = db.begin().await.expect("Error starting transaction");
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(false)
-        .delimiter(b'|')
-        .quoting(false)
-        .from_reader(entities_file);
-
-    let progress_bar = ProgressBar::new(line_count.try_into().unwrap());
-    progress_bar.set_style(
-        ProgressStyle::with_template(
-            "[{elapsed}+{eta}/{duration}] [{bar:40.cyan/blue}] {human_pos}/{human_len} ({per_sec}) {msg}",
-        )
-        .unwrap()
-        .progress_chars("#>-"),
-    );
-    progress_bar.set_message("EN.dat");
+/// Loads the records in `T::FILE_NAME` in a dump's zip archive into `T::TABLE`
+///
+/// The file is read straight out of the archive with a [`RecordReader`], so it's never extracted
+/// to disk. A missing file isn't an error, since daily dumps only contain the files that changed.
+/// Lines that can't be parsed are written to `rejected_records` instead of aborting the load.
+/// Nothing is committed, so the caller can load every table of an update in one transaction.
+///
+/// # Arguments
+///
+/// * `conn` - The connection to load into. This should be inside the update's transaction
+/// * `archive` - The dump's zip archive
+/// * `update_id` - The id of the update being loaded, recorded against any rejected lines
+/// * `clear_first` - Whether to delete everything in the table before loading. This should be
+///   true for weekly dumps and false for daily ones, which should call [`delete_licenses`] before
+///   loading any tables instead
+pub async fn load_table<T: UlsRecord>(
+    conn: &mut SqliteConnection,
+    archive: &mut ZipArchive<impl Read + Seek>,
+    update_id: u32,
+    clear_first: bool,
+) -> Result<LoadStats, LoadError> {
+    let file = match archive.by_name(T::FILE_NAME) {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => {
+            println!("{} not found, skipping", T::FILE_NAME);
+            return Ok(LoadStats::default());
+        }
+        Err(source) => {
+            return Err(LoadError::Zip {
+                file: T::FILE_NAME.to_string(),
+                source,
+            })
+        }
+    };
+
+    let progress_bar = new_bytes_progress_bar(file.size(), T::FILE_NAME);
+    let record_type = T::FILE_NAME.trim_end_matches(".dat");
+    let mut reader = RecordReader::new(BufReader::new(file), record_type, T::FIELD_COUNT);
 
     if clear_first {
-        QueryBuilder::new("DELETE FROM entities")
+        QueryBuilder::new(format!("DELETE FROM {}", T::TABLE))
             .build()
-            .execute(&mut transaction)
-            .await
-            .expect("Error deleting entities");
+            .execute(&mut *conn)
+            .await?;
     }
 
-    let chunk_size = BIND_LIMIT / 30;
-    for chunk in &reader.records().chunks(chunk_size) {
-        let chunk = chunk.collect::<Result<Vec<StringRecord>, _>>().unwrap();
-        let chunk = chunk.iter();
-
-        let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(INSERT_ENTITY_SQL);
-
-        query_builder.push_values(chunk, |mut builder, entry| {
-            let entity: Entity = entry.deserialize(None).expect("Error deserializing entry");
-            builder
-                .push_bind(entity.RecordType)
-                .push_bind(entity.UniqueSystemIdentifier)
-                .push_bind(entity.UlsFileNumber)
-                .push_bind(entity.EBFNumber)
-                .push_bind(entity.CallSign)
-                .push_bind(entity.EntityType)
-                .push_bind(entity.LicenseeId)
-                .push_bind(entity.EntityName)
-                .push_bind(entity.FirstName)
-                .push_bind(entity.MiddleInitial)
-                .push_bind(entity.LastName)
-                .push_bind(entity.Suffix)
-                .push_bind(entity.Phone)
-                .push_bind(entity.Fax)
-                .push_bind(entity.Email)
-                .push_bind(entity.StreetAddress)
-                .push_bind(entity.City)
-                .push_bind(entity.State)
-                .push_bind(entity.ZipCode)
-                .push_bind(entity.POBox)
-                .push_bind(entity.AttentionLine)
-                .push_bind(entity.SGIN)
-                .push_bind(entity.FRN)
-                .push_bind(entity.ApplicantTypeCode)
-                .push_bind(entity.ApplicantTypeCodeOther)
-                .push_bind(entity.StatusCode)
-                .push_bind(entity.StatusDate)
-                .push_bind(entity.ThreePointSevenGhzLicenseType)
-                .push_bind(entity.LinkedUniqueSystemIdentifier)
-                .push_bind(entity.LinkedCallsign);
-        });
-
-        query_builder
-            .build()
-            .execute(&mut transaction)
-            .await
-            .expect("Error executing query");
-        progress_bar.set_position(progress_bar.position() + chunk_size as u64);
+    let mut stats = LoadStats::default();
+    let chunk_size = BIND_LIMIT / T::COLUMNS;
+    loop {
+        let chunk = reader
+            .by_ref()
+            .take(chunk_size)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(LoadError::io(T::FILE_NAME))?;
+        if chunk.is_empty() {
+            break;
+        }
+        progress_bar.set_position(reader.bytes_read());
+
+        let mut rejected = Vec::new();
+        let entries = chunk
+            .into_iter()
+            .filter_map(|record| {
+                if record.fields != T::FIELD_COUNT {
+                    rejected.push(Rejected {
+                        line: record.line,
+                        raw_line: String::from_utf8_lossy(&record.data).into_owned(),
+                        error: format!(
+                            "expected {} fields, found {}",
+                            T::FIELD_COUNT,
+                            record.fields
+                        ),
+                    });
+                    return None;
+                }
+                match String::from_utf8(record.data) {
+                    Ok(data) => Some((
+                        record.line,
+                        StringRecord::from(data.split('|').collect_vec()),
+                    )),
+                    Err(err) => {
+                        rejected.push(Rejected {
+                            line: record.line,
+                            raw_line: String::from_utf8_lossy(err.as_bytes()).into_owned(),
+                            error: "Invalid UTF-8".to_string(),
+                        });
+                        None
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let records = entries
+            .iter()
+            .filter_map(
+                |(line, entry)| match entry.deserialize::<T::Record<'_>>(None) {
+                    Ok(record) => Some(record),
+                    Err(err) => {
+                        let line = *line;
+                        let source = match err.into_kind() {
+                            csv::ErrorKind::Deserialize { err, .. } => err,
+                            kind => DeserializeError::custom(format!("{:?}", kind)),
+                        };
+                        let err = LoadError::Deserialize {
+                            file: T::FILE_NAME.to_string(),
+                            line,
+                            source,
+                        };
+                        rejected.push(Rejected {
+                            line,
+                            raw_line: entry.iter().join("|"),
+                            error: err.to_string(),
+                        });
+                        None
+                    }
+                },
+            )
+            .collect::<Vec<_>>();
+
+        stats.records += records.len() as u64;
+        stats.rejected += rejected.len() as u64;
+
+        if !records.is_empty() {
+            let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(T::INSERT_SQL);
+            query_builder.push_values(records, |builder, record| T::bind(record, builder));
+            query_builder.build().execute(&mut *conn).await?;
+        }
+        insert_rejected(conn, update_id, T::FILE_NAME, rejected).await?;
     }
 
-    transaction
-        .commit()
-        .await
-        .expect("Error committing transaction");
-    std::fs::remove_file("EN.dat").expect("Error deleting EN.dat");
     progress_bar.finish();
-}
-
-pub async fn load_headers(db: &SqlitePool, clear_first: bool) {
-    let headers_file = File::open("HD.dat");
-    if headers_file.is_err() {
-        println!("HD.dat not found, skipping");
-        return;
+    if stats.rejected > 0 {
+        println!(
+            "Rejected {} records from {}, see rejected_records for details",
+            stats.rejected,
+            T::FILE_NAME
+        );
     }
-    let headers_file = headers_file.unwrap();
-    // let headers_file_meta = fs::metadata("HD.dat").expect("Error getting file metadata");
-    let line_count = std::io::BufReader::new(&headers_file).lines().count();
-    drop(headers_file);
-
-    let headers_file = File::open("HD.dat").expect("Error opening file");
-    let mut transaction = db.begin().await.expect("Error starting transaction");
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(false)
-        .delimiter(b'|')
-        .quoting(false)
-        .from_reader(headers_file);
-
-    let progress_bar = ProgressBar::new(line_count.try_into().unwrap());
-    progress_bar.set_style(
-        ProgressStyle::with_template(
-            "[{elapsed}+{eta}/{duration}] [{bar:40.cyan/blue}] {human_pos}/{human_len} ({per_sec}) {msg}",
-        )
-        .unwrap()
-        .progress_chars("#>-"),
-    );
-    progress_bar.set_message("HD.dat");
+    Ok(stats)
+}
 
-    if clear_first {
-        QueryBuilder::new("DELETE FROM headers")
-            .build()
-            .execute(&mut transaction)
-            .await
-            .expect("Error deleting headers");
+/// Deletes every license a daily dump touches from all of the record tables
+///
+/// Daily dumps carry the full set of records for every license they touch, so the old rows have
+/// to go before the new ones are loaded. That has to happen for every table up front, not just the
+/// tables with records for a license in this dump, or rows the FCC has since removed would stick
+/// around. Licenses are found by reading the unique system identifier out of every `.dat` file in
+/// the archive. Returns the number of licenses deleted.
+pub async fn delete_licenses(
+    conn: &mut SqliteConnection,
+    archive: &mut ZipArchive<impl Read + Seek>,
+) -> Result<usize, LoadError> {
+    let file_names = archive
+        .file_names()
+        .filter(|name| name.ends_with(".dat"))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+
+    let mut usis = HashSet::new();
+    for file_name in file_names {
+        let file = archive
+            .by_name(&file_name)
+            .map_err(|source| LoadError::Zip {
+                file: file_name.clone(),
+                source,
+            })?;
+        let prefix = format!("{}|", file_name.trim_end_matches(".dat"));
+        for line in BufReader::new(file).split(b'\n') {
+            let line = line.map_err(LoadError::io(&file_name))?;
+            // Lines that don't start with the record type are the rest of a record split across
+            // lines, which has already been counted
+            let Some(rest) = line.strip_prefix(prefix.as_bytes()) else {
+                continue;
+            };
+            let usi = rest.split(|c| *c == b'|').next().unwrap_or_default();
+            if let Some(usi) = std::str::from_utf8(usi)
+                .ok()
+                .and_then(|usi| usi.parse::<u32>().ok())
+            {
+                usis.insert(usi);
+            }
+        }
     }
 
-    let chunk_size = BIND_LIMIT / 60;
-    for chunk in &reader.records().chunks(chunk_size) {
-        let chunk = chunk.collect::<Result<Vec<StringRecord>, _>>().unwrap();
-        let chunk = chunk.iter();
-
-        let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(INSERT_HEADER_SQL);
-
-        query_builder.push_values(chunk, |mut builder, entry| {
-            let header: Header = entry.deserialize(None).expect("Error deserializing entry");
-            builder
-                .push_bind(header.RecordType)
-                .push_bind(header.UniqueSystemIdentifier)
-                .push_bind(header.UlsFileNumber)
-                .push_bind(header.EBFNumber)
-                .push_bind(header.CallSign)
-                .push_bind(header.LicenseStatus)
-                .push_bind(header.RadioServiceCode)
-                .push_bind(header.GrantDate)
-                .push_bind(header.ExpiredDate)
-                .push_bind(header.CancellationDate)
-                .push_bind(header.EligibilityRuleNumber)
-                .push_bind(header.Reserved)
-                .push_bind(header.Alien)
-                .push_bind(header.AlienGovernment)
-                .push_bind(header.AlienCorporation)
-                .push_bind(header.AlienOfficers)
-                .push_bind(header.AlienControl)
-                .push_bind(header.Revoked)
-                .push_bind(header.Convicted)
-                .push_bind(header.Adjudged)
-                .push_bind(header.Reserved2)
-                .push_bind(header.CommonCarrier)
-                .push_bind(header.NonCommonCarrier)
-                .push_bind(header.PrivateComm)
-                .push_bind(header.Fixed)
-                .push_bind(header.Mobile)
-                .push_bind(header.Radiolocation)
-                .push_bind(header.Sattelite)
-                .push_bind(header.DevelopmentalOrSta)
-                .push_bind(header.InterconnectedService)
-                .push_bind(header.CertifierFirstName)
-                .push_bind(header.CertifierMiddleInitial)
-                .push_bind(header.CertifierLastName)
-                .push_bind(header.CertifierSuffix)
-                .push_bind(header.CertifierTitle)
-                .push_bind(header.Female)
-                .push_bind(header.BlackOrAfricanAmerican)
-                .push_bind(header.NativeAmerican)
-                .push_bind(header.Hawaiian)
-                .push_bind(header.Asian)
-                .push_bind(header.White)
-                .push_bind(header.Hispanic)
-                .push_bind(header.EffectiveDate)
-                .push_bind(header.LastActionDate)
-                .push_bind(header.AuctionId)
-                .push_bind(header.BroadcastServicesRegulatoryStatus)
-                .push_bind(header.BandManagerRegulatoryStatus)
-                .push_bind(header.BroadcastServicesTypeOfRadioService)
-                .push_bind(header.AlienRuling)
-                .push_bind(header.LicenseeNameChange)
-                .push_bind(header.WhitespaceIndicator)
-                .push_bind(header.OperationRequirementChoice)
-                .push_bind(header.OperationRequirementAnswer)
-                .push_bind(header.DiscontinuationOfService)
-                .push_bind(header.RegulatoryCompliance)
-                .push_bind(header.EligibilityCertification900Mhz)
-                .push_bind(header.TransitionPlanCertification900Mhz)
-                .push_bind(header.ReturnSpectrumCertification900Mhz)
-                .push_bind(header.PaymentCertification900Mhz);
-        });
-
-        query_builder
-            .build()
-            .execute(&mut transaction)
-            .await
-            .expect("Error executing query");
-        progress_bar.set_position(progress_bar.position() + chunk_size as u64);
+    let usis = usis.into_iter().collect::<Vec<_>>();
+    for table in RECORD_TABLES {
+        for chunk in usis.chunks(BIND_LIMIT) {
+            let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(format!(
+                "DELETE FROM {} WHERE unique_system_identifier IN (",
+                table
+            ));
+            let mut separated = query_builder.separated(", ");
+            for usi in chunk {
+                separated.push_bind(*usi);
+            }
+            query_builder.push(")");
+            query_builder.build().execute(&mut *conn).await?;
+        }
     }
-
-    transaction
-        .commit()
-        .await
-        .expect("Error committing transaction");
-    std::fs::remove_file("HD.dat").expect("Error deleting HD.dat");
-    progress_bar.finish();
+    Ok(usis.len())
 }
 
-pub async fn load_history(db: &SqlitePool, clear_first: bool) {
-    let history_file = File::open("HS.dat");
-    if history_file.is_err() {
-        println!("No HS.dat file found, skipping");
-        return;
-    }
-    let history_file = history_file.unwrap();
-    // let history_file_meta = fs::metadata("HS.dat").expect("Error getting file metadata");
-    let line_count = std::io::BufReader::new(&history_file).lines().count();
-    drop(history_file);
-
-    let history_file = File::open("HS.dat").expect("Error opening file");
-    let mut transaction = db.begin().await.expect("Error starting transaction");
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(false)
-        .delimiter(b'|')
-        .quoting(false)
-        .from_reader(history_file);
-
-    let progress_bar = ProgressBar::new(line_count.try_into().unwrap());
-    progress_bar.set_style(
-        ProgressStyle::with_template(
-            "[{elapsed}+{eta}/{duration}] [{bar:40.cyan/blue}] {human_pos}/{human_len} ({per_sec}) {msg}",
-        )
-        .unwrap()
-        .progress_chars("#>-"),
-    );
-    progress_bar.set_message("HS.dat");
-
-    if clear_first {
-        QueryBuilder::new("DELETE FROM history")
-            .build()
-            .execute(&mut transaction)
-            .await
-            .expect("Error deleting history");
-    }
+/// A line that couldn't be loaded, waiting to be written to `rejected_records`
+struct Rejected {
+    line: u64,
+    raw_line: String,
+    error: String,
+}
 
-    let chunk_size = BIND_LIMIT / 6;
-    for chunk in &reader.records().chunks(chunk_size) {
-        let chunk = chunk.collect::<Result<Vec<StringRecord>, _>>().unwrap();
-        let chunk = chunk.iter();
-
-        let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(INSERT_HISTORY_SQL);
-
-        query_builder.push_values(chunk, |mut builder, entry| {
-            let history: History = entry.deserialize(None).expect("Error deserializing entry");
-            builder
-                .push_bind(history.RecordType)
-                .push_bind(history.UniqueSystemIdentifier)
-                .push_bind(history.UlsFileNumber)
-                .push_bind(history.CallSign)
-                .push_bind(history.LogDate)
-                .push_bind(history.Code);
-        });
-
-        query_builder
-            .build()
-            .execute(&mut transaction)
-            .await
-            .expect("Error executing query");
-        progress_bar.set_position(progress_bar.position() + chunk_size as u64);
+/// The name the special condition codes file is saved as, since the FCC doesn't give it one
+pub const SPECIAL_CONDITION_CODES_FILE_NAME: &str = "special_condition_codes.txt";
+
+/// Loads the special condition codes file at `path` into `special_condition_codes`
+///
+/// See [`crate::special_condition_codes`] for the format. Codes that can't be parsed are written
+/// to `rejected_records`, the same as [`load_table`].
+pub async fn load_special_condition_codes(
+    conn: &mut SqliteConnection,
+    path: &Path,
+    update_id: u32,
+    clear_first: bool,
+) -> Result<LoadStats, LoadError> {
+    const FILE_NAME: &str = SPECIAL_CONDITION_CODES_FILE_NAME;
+    let display_path = path.display().to_string();
+
+    if File::open(path).is_err() {
+        println!("No {} file found, skipping", display_path);
+        return Ok(LoadStats::default());
     }
 
-    transaction
-        .commit()
-        .await
-        .expect("Error committing transaction");
-    std::fs::remove_file("HS.dat").expect("Error deleting HS.dat");
-    progress_bar.finish();
-}
+    // This isn't from the ULS, so there's no telling what encoding it'll be in next
+    let raw = fs::read(path).map_err(LoadError::io(&display_path))?;
+    let records = special_condition_codes::split_records(&String::from_utf8_lossy(&raw));
 
-pub async fn load_license_attachments(db: &SqlitePool, clear_first: bool) {
-    let attachments_file = File::open("LA.dat");
-    if attachments_file.is_err() {
-        println!("No LA.dat file found, skipping");
-        return;
-    }
-    let attachments_file = attachments_file.unwrap();
-    // let attachments_file_meta = fs::metadata("LA.dat").expect("Error getting file metadata");
-    let line_count = std::io::BufReader::new(&attachments_file).lines().count();
-    drop(attachments_file);
-
-    let attachments_file = File::open("LA.dat").expect("Error opening file");
-    let mut transaction = db.begin().await.expect("Error starting transaction");
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(false)
-        .delimiter(b'|')
-        .quoting(false)
-        .from_reader(attachments_file);
-
-    let progress_bar = ProgressBar::new(line_count.try_into().unwrap());
-    progress_bar.set_style(
-        ProgressStyle::with_template(
-            "[{elapsed}+{eta}/{duration}] [{bar:40.cyan/blue}] {human_pos}/{human_len} ({per_sec}) {msg}",
-        )
-        .unwrap()
-        .progress_chars("#>-"),
-    );
-    progress_bar.set_message("LA.dat");
+    let progress_bar = new_progress_bar(records.len() as u64, FILE_NAME);
 
     if clear_first {
-        QueryBuilder::new("DELETE FROM license_attachments")
+        QueryBuilder::new("DELETE FROM special_condition_codes")
             .build()
-            .execute(&mut transaction)
-            .await
-            .expect("Error deleting license_attachments");
+            .execute(&mut *conn)
+            .await?;
     }
 
+    let mut stats = LoadStats::default();
     let chunk_size = BIND_LIMIT / 8;
-    for chunk in &reader.records().chunks(chunk_size) {
-        let chunk = chunk.collect::<Result<Vec<StringRecord>, _>>().unwrap();
-        let chunk = chunk.iter();
-
-        let mut query_builder: QueryBuilder<Sqlite> =
-            QueryBuilder::new(INSERT_LICENSE_ATTACHMENT_SQL);
-
-        query_builder.push_values(chunk, |mut builder, entry| {
-            let attachment: LicenseAttachment =
-                entry.deserialize(None).expect("Error deserializing entry");
-            builder
-                .push_bind(attachment.RecordType)
-                .push_bind(attachment.UniqueSystemIdentifier)
-                .push_bind(attachment.CallSign)
-                .push_bind(attachment.AttachmentCode)
-                .push_bind(attachment.AttachmentDescription)
-                .push_bind(attachment.AttachmentDate)
-                .push_bind(attachment.AttachmentFileName)
-                .push_bind(attachment.ActionPerformed);
-        });
-
-        query_builder
-            .build()
-            .execute(&mut transaction)
-            .await
-            .expect("Error executing query");
-        progress_bar.set_position(progress_bar.position() + chunk_size as u64);
+    for (chunk_index, chunk) in records.chunks(chunk_size).enumerate() {
+        let mut rejected = Vec::new();
+        let codes = chunk
+            .iter()
+            .enumerate()
+            .filter_map(
+                |(i, record)| match special_condition_codes::parse_record(record) {
+                    Ok(code) => Some(code),
+                    Err(err) => {
+                        rejected.push(Rejected {
+                            line: (chunk_index * chunk_size + i + 1) as u64,
+                            raw_line: record.clone(),
+                            error: err.to_string(),
+                        });
+                        None
+                    }
+                },
+            )
+            .collect::<Vec<_>>();
+
+        stats.records += codes.len() as u64;
+        stats.rejected += rejected.len() as u64;
+
+        if !codes.is_empty() {
+            let mut query_builder: QueryBuilder<Sqlite> =
+                QueryBuilder::new(INSERT_SPECIAL_CONDITION_CODE_SQL);
+            query_builder.push_values(codes, |mut builder, code| {
+                let [text_1, text_2, text_3, text_4, text_5] = code.text;
+                builder
+                    .push_bind(code.code)
+                    .push_bind(code.service)
+                    .push_bind(text_1)
+                    .push_bind(text_2)
+                    .push_bind(text_3)
+                    .push_bind(text_4)
+                    .push_bind(text_5)
+                    .push_bind(code.flag);
+            });
+            query_builder.build().execute(&mut *conn).await?;
+        }
+        insert_rejected(conn, update_id, FILE_NAME, rejected).await?;
+        progress_bar.inc(chunk.len() as u64);
     }
 
-    transaction
-        .commit()
-        .await
-        .expect("Error committing transaction");
-    std::fs::remove_file("LA.dat").expect("Error deleting LA.dat");
     progress_bar.finish();
-}
-
-pub async fn load_special_conditions(db: &SqlitePool, clear_first: bool) {
-    let conditions_file = File::open("SC.dat");
-    if conditions_file.is_err() {
-        println!("No SC.dat file found, skipping");
-        return;
-    }
-    let conditions_file = conditions_file.unwrap();
-    // let conditions_file_meta = fs::metadata("SC.dat").expect("Error getting file metadata");
-    let line_count = std::io::BufReader::new(&conditions_file).lines().count();
-    drop(conditions_file);
-
-    let conditions_file = File::open("SC.dat").expect("Error opening file");
-    let mut transaction = db.begin().await.expect("Error starting transaction");
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(false)
-        .delimiter(b'|')
-        .quoting(false)
-        .from_reader(conditions_file);
-
-    let progress_bar = ProgressBar::new(line_count.try_into().unwrap());
-    progress_bar.set_style(
-        ProgressStyle::with_template(
-            "[{elapsed}+{eta}/{duration}] [{bar:40.cyan/blue}] {human_pos}/{human_len} ({per_sec}) {msg}",
-        )
-        .unwrap()
-        .progress_chars("#>-"),
-    );
-    progress_bar.set_message("SC.dat");
-
-    if clear_first {
-        QueryBuilder::new("DELETE FROM special_conditions")
-            .build()
-            .execute(&mut transaction)
-            .await
-            .expect("Error deleting special_conditions");
+    if stats.rejected > 0 {
+        println!(
+            "Rejected {} records from {}, see rejected_records for details",
+            stats.rejected, FILE_NAME
+        );
     }
+    Ok(stats)
+}
 
-    let chunk_size = BIND_LIMIT / 9;
-    for chunk in &reader.records().chunks(chunk_size) {
-        let chunk = chunk.collect::<Result<Vec<StringRecord>, _>>().unwrap();
-        let chunk = chunk.iter();
-
-        let mut query_builder: QueryBuilder<Sqlite> =
-            QueryBuilder::new(INSERT_SPECIAL_CONDITION_SQL);
-
-        query_builder.push_values(chunk, |mut builder, entry| {
-            let condition: SpecialCondition =
-                entry.deserialize(None).expect("Error deserializing entry");
-            builder
-                .push_bind(condition.RecordType)
-                .push_bind(condition.UniqueSystemIdentifier)
-                .push_bind(condition.UlsFileNumber)
-                .push_bind(condition.EBFNumber)
-                .push_bind(condition.CallSign)
-                .push_bind(condition.SpecialConditionType)
-                .push_bind(condition.SpecialConditionCode)
-                .push_bind(condition.StatusCode)
-                .push_bind(condition.StatusDate);
-        });
-
-        query_builder
-            .build()
-            .execute(&mut transaction)
-            .await
-            .expect("Error executing query");
-        progress_bar.set_position(progress_bar.position() + chunk_size as u64);
+async fn insert_rejected(
+    conn: &mut SqliteConnection,
+    update_id: u32,
+    file_name: &str,
+    rejected: Vec<Rejected>,
+) -> Result<(), LoadError> {
+    if rejected.is_empty() {
+        return Ok(());
     }
 
-    transaction
-        .commit()
-        .await
-        .expect("Error committing transaction");
-    std::fs::remove_file("SC.dat").expect("Error deleting SC.dat");
-    progress_bar.finish();
+    let mut query_builder: QueryBuilder<Sqlite> = QueryBuilder::new(INSERT_REJECTED_RECORD_SQL);
+    query_builder.push_values(rejected, |mut builder, rejected| {
+        builder
+            .push_bind(update_id)
+            .push_bind(file_name.to_string())
+            .push_bind(rejected.line as i64)
+            .push_bind(rejected.raw_line)
+            .push_bind(rejected.error);
+    });
+    query_builder.build().execute(&mut *conn).await?;
+    Ok(())
 }
 
-pub async fn load_special_conditions_free_form(db: &SqlitePool, clear_first: bool) {
-    let conditions_file = File::open("SF.dat");
-    if conditions_file.is_err() {
-        println!("No SF.dat file found, skipping");
-        return;
-    }
-    let conditions_file = conditions_file.unwrap();
-    // let conditions_file_meta = fs::metadata("SF.dat").expect("Error getting file metadata");
-    let line_count = std::io::BufReader::new(&conditions_file).lines().count();
-    drop(conditions_file);
-
-    let conditions_file = File::open("SF.dat").expect("Error opening file");
-    let mut transaction = db.begin().await.expect("Error starting transaction");
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(false)
-        .delimiter(b'|')
-        .quoting(false)
-        .from_reader(conditions_file);
-
-    let progress_bar = ProgressBar::new(line_count.try_into().unwrap());
+fn new_progress_bar(len: u64, message: &'static str) -> ProgressBar {
+    let progress_bar = ProgressBar::new(len);
     progress_bar.set_style(
         ProgressStyle::with_template(
             "[{elapsed}+{eta}/{duration}] [{bar:40.cyan/blue}] {human_pos}/{human_len} ({per_sec}) {msg}",
@@ -657,130 +449,21 @@ pub async fn load_special_conditions_free_form(db: &SqlitePool, clear_first: boo
         .unwrap()
         .progress_chars("#>-"),
     );
-    progress_bar.set_message("SF.dat");
-
-    if clear_first {
-        QueryBuilder::new("DELETE FROM special_conditions_free_form")
-            .build()
-            .execute(&mut transaction)
-            .await
-            .expect("Error deleting special_conditions_free_form");
-    }
-
-    let chunk_size = BIND_LIMIT / 11;
-    for chunk in &reader.records().chunks(chunk_size) {
-        let chunk = chunk.collect::<Result<Vec<StringRecord>, _>>().unwrap();
-        let chunk = chunk.iter();
-
-        let mut query_builder: QueryBuilder<Sqlite> =
-            QueryBuilder::new(INSERT_SPECIAL_CONDITION_FREE_FORM_SQL);
-
-        query_builder.push_values(chunk, |mut builder, entry| {
-            let condition: SpecialConditionFreeForm =
-                entry.deserialize(None).expect("Error deserializing entry");
-            builder
-                .push_bind(condition.RecordType)
-                .push_bind(condition.UniqueSystemIdentifier)
-                .push_bind(condition.UlsFileNumber)
-                .push_bind(condition.EBFNumber)
-                .push_bind(condition.CallSign)
-                .push_bind(condition.LicenseFreeFormType)
-                .push_bind(condition.UniqueLicenseFreeFormIdentifier)
-                .push_bind(condition.SequenceNumber)
-                .push_bind(condition.LicenseFreeFormCondition)
-                .push_bind(condition.StatusCode)
-                .push_bind(condition.StatusDate);
-        });
-
-        query_builder
-            .build()
-            .execute(&mut transaction)
-            .await
-            .expect("Error executing query");
-        progress_bar.set_position(progress_bar.position() + chunk_size as u64);
-    }
-
-    transaction
-        .commit()
-        .await
-        .expect("Error committing transaction");
-    std::fs::remove_file("SF.dat").expect("Error deleting SF.dat");
-    progress_bar.finish();
+    progress_bar.set_message(message);
+    progress_bar
 }
 
-pub async fn load_special_condition_codes(db: &SqlitePool, clear_first: bool) {
-    let codes_file = File::open("special_condition_codes.txt");
-    if codes_file.is_err() {
-        println!("No special_condition_codes.txt file found, skipping");
-        return;
-    }
-    let codes_file = codes_file.unwrap();
-    // let history_file_meta = fs::metadata("special_condition_codes.txt").expect("Error getting file metadata");
-    let line_count = std::io::BufReader::new(&codes_file).lines().count();
-    drop(codes_file);
-
-    let codes_file = File::open("special_condition_codes.txt").expect("Error opening file");
-    let mut transaction = db.begin().await.expect("Error starting transaction");
-    let mut reader = csv::ReaderBuilder::new()
-        .has_headers(false)
-        .delimiter(b'|')
-        .quoting(true)
-        .from_reader(codes_file);
-
-    let progress_bar = ProgressBar::new(line_count.try_into().unwrap());
+/// A progress bar counting bytes rather than records, for files we can't count the records in
+/// without reading them twice
+fn new_bytes_progress_bar(len: u64, message: &'static str) -> ProgressBar {
+    let progress_bar = ProgressBar::new(len);
     progress_bar.set_style(
         ProgressStyle::with_template(
-            "[{elapsed}+{eta}/{duration}] [{bar:40.cyan/blue}] {human_pos}/{human_len} ({per_sec}) {msg}",
+            "[{elapsed}+{eta}/{duration}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}) {msg}",
         )
         .unwrap()
         .progress_chars("#>-"),
     );
-    progress_bar.set_message("special_condition_codes.txt");
-
-    if clear_first {
-        QueryBuilder::new("DELETE FROM special_condition_codes")
-            .build()
-            .execute(&mut transaction)
-            .await
-            .expect("Error deleting special_condition_codes");
-    }
-
-    let chunk_size = BIND_LIMIT / 4;
-    for chunk in &reader.records().chunks(chunk_size) {
-        let chunk = chunk.collect::<Result<Vec<StringRecord>, _>>().unwrap();
-        let chunk = chunk.iter();
-
-        let mut query_builder: QueryBuilder<Sqlite> =
-            QueryBuilder::new(INSERT_SPECIAL_CONDITION_CODE_SQL);
-
-        query_builder.push_values(chunk, |mut builder, entry| {
-            builder
-                .push_bind(entry.get(0))
-                .push_bind(entry.get(1))
-                .push_bind(format!(
-                    "{} {} {} {} {}",
-                    entry.get(2).unwrap_or_default(),
-                    entry.get(3).unwrap_or_default(),
-                    entry.get(4).unwrap_or_default(),
-                    entry.get(5).unwrap_or_default(),
-                    entry.get(6).unwrap_or_default()
-                ))
-                .push_bind(entry.get(7));
-        });
-
-        query_builder
-            .build()
-            .execute(&mut transaction)
-            .await
-            .expect("Error executing query");
-        progress_bar.set_position(progress_bar.position() + chunk_size as u64);
-    }
-
-    transaction
-        .commit()
-        .await
-        .expect("Error committing transaction");
-    std::fs::remove_file("special_condition_codes.txt")
-        .expect("Error deleting special_condition_codes.txt");
-    progress_bar.finish();
+    progress_bar.set_message(message);
+    progress_bar
 }
@@ -1,6 +1,6 @@
 use crate::types::Update;
 
-use sqlx::SqlitePool;
+use sqlx::{Executor, Sqlite, SqlitePool};
 
 #[allow(dead_code)]
 pub enum UpdateType {
@@ -24,8 +24,30 @@ pub async fn get_last_update(
     Ok(update)
 }
 
-pub async fn insert_update(db: &SqlitePool, update: &Update) -> Result<(), sqlx::Error> {
-    sqlx::query("INSERT INTO updates (daily, weekly, date) VALUES (?, ?, ?)")
+pub async fn get_updates(db: &SqlitePool) -> Result<Vec<Update>, sqlx::Error> {
+    sqlx::query_as::<_, Update>("SELECT * FROM updates ORDER BY id")
+        .fetch_all(db)
+        .await
+}
+
+/// Returns the id the next update should be recorded with, so rows written while loading it
+/// (such as `rejected_records`) can refer to it before it's inserted
+pub async fn next_update_id<'c, E>(db: E) -> Result<u32, sqlx::Error>
+where
+    E: Executor<'c, Database = Sqlite>,
+{
+    let (id,): (u32,) = sqlx::query_as("SELECT COALESCE(MAX(id), 0) + 1 FROM updates")
+        .fetch_one(db)
+        .await?;
+    Ok(id)
+}
+
+pub async fn insert_update<'c, E>(db: E, update: &Update) -> Result<(), sqlx::Error>
+where
+    E: Executor<'c, Database = Sqlite>,
+{
+    sqlx::query("INSERT INTO updates (id, daily, weekly, date) VALUES (?, ?, ?, ?)")
+        .bind(update.id)
         .bind(update.daily)
         .bind(update.weekly)
         .bind(update.date)
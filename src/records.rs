@@ -0,0 +1,144 @@
+//! Splitting the `.dat` files in a ULS dump into records
+//!
+//! Records are supposed to be one per line, but the FCC doesn't escape newlines inside fields.
+//! They show up as `\r\r\n`, `\r\n`, or a bare `\n`, so line endings can't be trusted to mark
+//! where records end. Instead, every record starts with its record type (like `CO|`) and has a
+//! known number of fields, so lines are joined back together until they add up to a whole record.
+
+use std::io::{self, BufRead};
+
+/// A single record from a `.dat` file, with any newlines inside it replaced by spaces
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// The line the record starts on, counting from 1
+    pub line: u64,
+    pub data: Vec<u8>,
+    /// The number of `|` separated fields in the record. This is different from the reader's
+    /// `field_count` for records that couldn't be put back together.
+    pub fields: usize,
+}
+
+/// Reads records one at a time, so a file never has to be held in memory all at once
+///
+/// A line is joined onto the record before it as long as that doesn't take it over `field_count`
+/// fields, and either the record is still short or the line doesn't start with the record type at
+/// all. Records that end up with the wrong number of fields, including lines that don't start with
+/// the record type but won't fit on the record before them, are returned as they are for the
+/// caller to report.
+///
+/// # Examples
+/// ```
+/// use artemis::records::RecordReader;
+///
+/// let raw = "CO|1|one  \r\r\ncomment|x\r\nCO|2|another|x\r\nCO|3|broken\n\r\nCO|4|last|x\nend\nstray|y";
+/// let records = RecordReader::new(raw.as_bytes(), "CO", 4)
+///     .map(|record| record.unwrap())
+///     .collect::<Vec<_>>();
+/// assert_eq!(records.len(), 5);
+/// assert_eq!(records[0].data, b"CO|1|one comment|x");
+/// assert_eq!(records[1].line, 3);
+///
+/// // A record that can't be completed without eating the next one is left short
+/// assert_eq!(records[2].data, b"CO|3|broken");
+/// assert_eq!(records[2].fields, 3);
+///
+/// // A line without the record type belongs to the record before it if it fits
+/// assert_eq!(records[3].line, 6);
+/// assert_eq!(records[3].data, b"CO|4|last|x end");
+///
+/// // And is left on its own if it doesn't
+/// assert_eq!(records[4].data, b"stray|y");
+/// assert_eq!(records[4].fields, 2);
+/// ```
+pub struct RecordReader<R> {
+    reader: R,
+    prefix: Vec<u8>,
+    field_count: usize,
+    /// The next line, read ahead to see whether it belongs to the current record
+    next_line: Option<Vec<u8>>,
+    lines_read: u64,
+    bytes_read: u64,
+}
+
+impl<R: BufRead> RecordReader<R> {
+    /// Creates a reader for records of `record_type`, such as `HD`, with `field_count` fields each
+    pub fn new(reader: R, record_type: &str, field_count: usize) -> Self {
+        RecordReader {
+            reader,
+            prefix: format!("{}|", record_type).into_bytes(),
+            field_count,
+            next_line: None,
+            lines_read: 0,
+            bytes_read: 0,
+        }
+    }
+
+    /// How far into the file the reader is, for progress bars
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    /// Reads the next line without its line ending, skipping blank lines
+    fn read_line(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            let mut line = Vec::new();
+            let read = self.reader.read_until(b'\n', &mut line)?;
+            if read == 0 {
+                return Ok(None);
+            }
+            self.lines_read += 1;
+            self.bytes_read += read as u64;
+
+            while line.last().is_some_and(|c| *c == b'\n' || *c == b'\r') {
+                line.pop();
+            }
+            if !line.is_empty() {
+                return Ok(Some(line));
+            }
+        }
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<Record>> {
+        let first = match self.next_line.take() {
+            Some(line) => line,
+            None => match self.read_line()? {
+                Some(line) => line,
+                None => return Ok(None),
+            },
+        };
+        let mut record = Record {
+            line: self.lines_read,
+            fields: count_fields(&first),
+            data: first,
+        };
+
+        while let Some(line) = self.read_line()? {
+            let fields = record.fields + count_fields(&line) - 1;
+            let continues = fields <= self.field_count
+                && (record.fields < self.field_count || !line.starts_with(&self.prefix));
+            if !continues {
+                self.next_line = Some(line);
+                break;
+            }
+
+            let trimmed = record.data.trim_ascii_end().len();
+            record.data.truncate(trimmed);
+            record.data.push(b' ');
+            record.data.extend_from_slice(&line);
+            record.fields = fields;
+        }
+        Ok(Some(record))
+    }
+}
+
+fn count_fields(line: &[u8]) -> usize {
+    line.iter().filter(|c| **c == b'|').count() + 1
+}
+
+impl<R: BufRead> Iterator for RecordReader<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
@@ -0,0 +1,531 @@
+//! Searching licensees, one row per FRN, with keyset pagination
+//!
+//! Pages are walked with an opaque cursor rather than an offset, so results don't shift around
+//! if the database is replaced between requests and deep pages don't get slower.
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, QueryBuilder, Sqlite, SqlitePool};
+use std::fmt;
+
+use crate::codes::{LicenseStatus, OperatorClass};
+
+/// The most results a single page can have
+pub const MAX_LIMIT: u32 = 100;
+const DEFAULT_LIMIT: u32 = 20;
+
+/// A licensee and every call they've held
+#[derive(Debug, FromRow, Serialize)]
+pub struct SearchResult {
+    pub call_sign: String,
+    pub operator_class: Option<OperatorClass>,
+    pub frn: String,
+    pub first_name: String,
+    pub mi: String,
+    pub last_name: String,
+    pub city: String,
+    pub state: String,
+    pub license_status: LicenseStatus,
+    pub grant_date: String,
+    pub expired_date: String,
+    pub cancellation_date: String,
+    pub call_count: i64,
+    pub call_history: String,
+    /// How well the licensee matched `q`, where lower is better, or `None` without `q`
+    pub rank: Option<f64>,
+}
+
+/// What to order results by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Sort {
+    #[default]
+    GrantDate,
+    ExpiredDate,
+    CallSign,
+    LastName,
+    /// Best match first, which is the default with `q`
+    Relevance,
+}
+
+impl Sort {
+    fn column(self) -> &'static str {
+        match self {
+            Sort::GrantDate => "COALESCE(results.grant_date, '')",
+            Sort::ExpiredDate => "COALESCE(results.expired_date, '')",
+            Sort::CallSign => "COALESCE(results.call_sign, '')",
+            Sort::LastName => "COALESCE(results.last_name, '')",
+            Sort::Relevance => "COALESCE(results.rank, 0)",
+        }
+    }
+
+    /// Newest first for dates, alphabetical for names, and best first for relevance
+    fn default_order(self) -> Order {
+        match self {
+            Sort::GrantDate | Sort::ExpiredDate => Order::Desc,
+            Sort::CallSign | Sort::LastName | Sort::Relevance => Order::Asc,
+        }
+    }
+
+    fn value(self, result: &SearchResult) -> String {
+        match self {
+            Sort::GrantDate => result.grant_date.clone(),
+            Sort::ExpiredDate => result.expired_date.clone(),
+            Sort::CallSign => result.call_sign.clone(),
+            Sort::LastName => result.last_name.clone(),
+            Sort::Relevance => result.rank.unwrap_or(0.0).to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
+/// What to search for, where text filters are `LIKE` patterns
+///
+/// A licensee matches if any one of their licenses matches every filter given.
+#[derive(Debug, Default, Deserialize)]
+pub struct SearchParams {
+    /// Words to full-text search names, cities and attention lines for, each matching as a prefix
+    pub q: Option<String>,
+    /// A last name to match phonetically, by its Soundex code
+    pub sounds_like: Option<String>,
+    pub call_sign: Option<String>,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    /// The name of a club or other organization
+    pub entity_name: Option<String>,
+    pub city: Option<String>,
+    /// A two letter state code, which has to match exactly
+    pub state: Option<String>,
+    pub zip_code: Option<String>,
+    /// An operator class code or name, such as `E` or `extra`
+    pub operator_class: Option<OperatorClass>,
+    /// A license status code or name, such as `A` or `active`
+    pub license_status: Option<LicenseStatus>,
+    pub granted_after: Option<NaiveDate>,
+    pub granted_before: Option<NaiveDate>,
+    pub expires_after: Option<NaiveDate>,
+    pub expires_before: Option<NaiveDate>,
+    pub sort: Option<Sort>,
+    pub order: Option<Order>,
+    pub limit: Option<u32>,
+    /// The `next_cursor` from the previous page
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchPage {
+    pub results: Vec<SearchResult>,
+    /// How many results there are across every page
+    pub total: i64,
+    /// Pass this as `cursor` to get the next page, or `None` on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Where the previous page left off, which is the sort value and FRN of its last result
+///
+/// The sort and order are kept too, so a cursor can't be used with a different sort than the one
+/// it came from.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cursor {
+    pub sort: Sort,
+    pub order: Order,
+    pub value: String,
+    pub frn: String,
+}
+
+impl Cursor {
+    /// Encodes the cursor as an opaque string
+    ///
+    /// # Examples
+    /// ```
+    /// use artemis::search::{Cursor, Order, Sort};
+    ///
+    /// let cursor = Cursor {
+    ///     sort: Sort::LastName,
+    ///     order: Order::Asc,
+    ///     value: "Smith".to_string(),
+    ///     frn: "0001234567".to_string(),
+    /// };
+    /// let encoded = cursor.encode();
+    /// assert!(encoded.chars().all(|c| c.is_ascii_hexdigit()));
+    /// assert_eq!(Cursor::decode(&encoded), Some(cursor));
+    ///
+    /// assert_eq!(Cursor::decode("not a cursor"), None);
+    /// ```
+    pub fn encode(&self) -> String {
+        serde_json::to_vec(self)
+            .unwrap()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Decodes a cursor from [`Cursor::encode`], returning `None` if it's been tampered with
+    pub fn decode(encoded: &str) -> Option<Cursor> {
+        if !encoded.len().is_multiple_of(2) || !encoded.is_ascii() {
+            return None;
+        }
+        let bytes = (0..encoded.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&encoded[i..i + 2], 16).ok())
+            .collect::<Option<Vec<_>>>()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
+#[derive(Debug)]
+pub enum SearchError {
+    /// The cursor couldn't be decoded, or came from a search with a different sort
+    InvalidCursor,
+    /// A filter had a code we don't know, named here
+    InvalidFilter(&'static str),
+    Sql(sqlx::Error),
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchError::InvalidCursor => write!(f, "Invalid cursor"),
+            SearchError::InvalidFilter(filter) => write!(f, "Invalid {}", filter),
+            SearchError::Sql(err) => write!(f, "Error searching: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SearchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SearchError::InvalidCursor | SearchError::InvalidFilter(_) => None,
+            SearchError::Sql(err) => Some(err),
+        }
+    }
+}
+
+impl From<sqlx::Error> for SearchError {
+    fn from(err: sqlx::Error) -> Self {
+        SearchError::Sql(err)
+    }
+}
+
+/// Returns the American Soundex code for a name, or an empty string if it has no letters
+///
+/// # Examples
+/// ```
+/// use artemis::search::soundex;
+///
+/// assert_eq!(soundex("Robert"), "R163");
+/// assert_eq!(soundex("Rupert"), "R163");
+/// assert_eq!(soundex("Ashcraft"), "A261");
+/// assert_eq!(soundex("Tymczak"), "T522");
+/// assert_eq!(soundex("Pfister"), "P236");
+/// assert_eq!(soundex("o'brien"), "O165");
+/// assert_eq!(soundex("Lee"), "L000");
+/// assert_eq!(soundex(""), "");
+/// ```
+pub fn soundex(name: &str) -> String {
+    fn digit(letter: u8) -> u8 {
+        match letter {
+            b'B' | b'F' | b'P' | b'V' => b'1',
+            b'C' | b'G' | b'J' | b'K' | b'Q' | b'S' | b'X' | b'Z' => b'2',
+            b'D' | b'T' => b'3',
+            b'L' => b'4',
+            b'M' | b'N' => b'5',
+            b'R' => b'6',
+            // Vowels, plus H and W which are handled separately
+            _ => b'0',
+        }
+    }
+
+    let mut letters = name
+        .bytes()
+        .filter(u8::is_ascii_alphabetic)
+        .map(|letter| letter.to_ascii_uppercase());
+    let first = match letters.next() {
+        Some(first) => first,
+        None => return String::new(),
+    };
+
+    let mut code = vec![first];
+    let mut last = digit(first);
+    for letter in letters {
+        // H and W don't separate letters with the same digit, but vowels do
+        if letter == b'H' || letter == b'W' {
+            continue;
+        }
+        let current = digit(letter);
+        if current != b'0' && current != last {
+            code.push(current);
+            if code.len() == 4 {
+                break;
+            }
+        }
+        last = current;
+    }
+    code.resize(4, b'0');
+    String::from_utf8(code).unwrap()
+}
+
+/// Turns a free-text search into an FTS5 query matching every word as a prefix
+///
+/// Anything but letters and digits is dropped, so users can't write FTS5 syntax by accident.
+///
+/// # Examples
+/// ```
+/// use artemis::search::fts_query;
+///
+/// assert_eq!(fts_query("Radio Club of Amer").as_deref(), Some(r#""radio"* "club"* "of"* "amer"*"#));
+/// assert_eq!(fts_query(r#"o'brien NEAR("x")"#).as_deref(), Some(r#""obrien"* "nearx"*"#));
+/// assert_eq!(fts_query(" * "), None);
+/// ```
+pub fn fts_query(q: &str) -> Option<String> {
+    let terms = q
+        .split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{}\"*", word))
+        .collect::<Vec<_>>();
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+/// Pushes the query for every matching licensee, grouped by FRN, without any paging
+fn push_results_query(query_builder: &mut QueryBuilder<'_, Sqlite>, params: &SearchParams) {
+    // Every entity of a licensee can match, so their best match is their rank
+    let fts_query = params.q.as_deref().and_then(fts_query);
+    if let Some(fts_query) = &fts_query {
+        query_builder.push(
+            "WITH matches AS (
+                SELECT entities.frn, MIN(entities_fts.rank) AS rank
+                FROM entities_fts
+                JOIN entities ON entities.unique_system_identifier = entities_fts.rowid
+                WHERE entities_fts MATCH ",
+        );
+        query_builder.push_bind(fts_query.clone());
+        query_builder.push(
+            "
+                GROUP BY entities.frn
+            )
+            ",
+        );
+    }
+    query_builder.push(
+        "SELECT
+            amateurs.call_sign,
+            amateurs.operator_class,
+            entities.frn,
+            entities.first_name,
+            entities.mi,
+            entities.last_name,
+            entities.city,
+            entities.state,
+            headers.license_status,
+            MAX(headers.grant_date) AS grant_date,
+            headers.expired_date,
+            headers.cancellation_date,
+            count(amateurs.call_sign) AS call_count,
+            group_concat(amateurs.call_sign, ',') AS call_history,",
+    );
+    if fts_query.is_some() {
+        query_builder.push(
+            "
+            matches.rank AS rank
+        FROM entities
+        JOIN matches ON matches.frn = entities.frn",
+        );
+    } else {
+        query_builder.push(
+            "
+            NULL AS rank
+        FROM entities",
+        );
+    }
+    query_builder.push(
+        "
+        JOIN amateurs
+            ON amateurs.unique_system_identifier = entities.unique_system_identifier
+        JOIN headers
+            ON amateurs.unique_system_identifier = headers.unique_system_identifier
+        WHERE
+            entities.frn IN (
+                SELECT entities.frn
+                FROM entities
+                JOIN amateurs
+                    ON amateurs.unique_system_identifier = entities.unique_system_identifier
+                JOIN headers
+                    ON headers.unique_system_identifier = entities.unique_system_identifier
+                WHERE 1 = 1",
+    );
+
+    let mut filter = |condition: &str, value: Option<String>| {
+        if let Some(value) = value {
+            query_builder.push(format!(" AND {} ", condition));
+            query_builder.push_bind(value);
+        }
+    };
+    filter("entities.call_sign LIKE", params.call_sign.clone());
+    filter("entities.first_name LIKE", params.first_name.clone());
+    filter("entities.last_name LIKE", params.last_name.clone());
+    filter("entities.entity_name LIKE", params.entity_name.clone());
+    filter(
+        "entities.last_name_soundex =",
+        params.sounds_like.as_deref().map(soundex),
+    );
+    filter("entities.city LIKE", params.city.clone());
+    filter(
+        "entities.state =",
+        params.state.as_ref().map(|state| state.to_uppercase()),
+    );
+    filter("entities.zip_code LIKE", params.zip_code.clone());
+    filter(
+        "amateurs.operator_class =",
+        params
+            .operator_class
+            .as_ref()
+            .map(|class| class.code().to_string()),
+    );
+    filter(
+        "headers.license_status =",
+        params
+            .license_status
+            .as_ref()
+            .map(|status| status.code().to_string()),
+    );
+    // Dates are stored as YYYY-MM-DD, so they compare correctly as strings
+    filter("headers.grant_date >=", params.granted_after.map(date));
+    filter("headers.grant_date <=", params.granted_before.map(date));
+    filter("headers.expired_date >=", params.expires_after.map(date));
+    filter("headers.expired_date <=", params.expires_before.map(date));
+
+    query_builder.push(
+        "
+            )
+            AND entities.frn != ''
+        GROUP BY entities.frn",
+    );
+}
+
+fn date(date: NaiveDate) -> String {
+    date.format("%Y-%m-%d").to_string()
+}
+
+/// Returns a page of licensees matching the search, and a cursor for the next one
+pub async fn search(db: &SqlitePool, params: &SearchParams) -> Result<SearchPage, SearchError> {
+    if let Some(OperatorClass::Unknown(_)) = params.operator_class {
+        return Err(SearchError::InvalidFilter("operator_class"));
+    }
+    if let Some(LicenseStatus::Unknown(_)) = params.license_status {
+        return Err(SearchError::InvalidFilter("license_status"));
+    }
+    if params.q.as_deref().is_some_and(|q| fts_query(q).is_none()) {
+        return Err(SearchError::InvalidFilter("q"));
+    }
+    if params
+        .sounds_like
+        .as_deref()
+        .is_some_and(|name| soundex(name).is_empty())
+    {
+        return Err(SearchError::InvalidFilter("sounds_like"));
+    }
+
+    let cursor = match &params.cursor {
+        Some(cursor) => Some(Cursor::decode(cursor).ok_or(SearchError::InvalidCursor)?),
+        None => None,
+    };
+    let sort = params
+        .sort
+        .or(cursor.as_ref().map(|cursor| cursor.sort))
+        .unwrap_or(if params.q.is_some() {
+            Sort::Relevance
+        } else {
+            Sort::default()
+        });
+    let order = params
+        .order
+        .or(cursor.as_ref().map(|cursor| cursor.order))
+        .unwrap_or(sort.default_order());
+    if let Some(cursor) = &cursor {
+        if cursor.sort != sort || cursor.order != order {
+            return Err(SearchError::InvalidCursor);
+        }
+    }
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let mut count_query: QueryBuilder<Sqlite> = QueryBuilder::new("SELECT COUNT(*) FROM (");
+    push_results_query(&mut count_query, params);
+    count_query.push(")");
+    let (total,): (i64,) = count_query.build_query_as().fetch_one(db).await?;
+
+    let (direction, comparison) = match order {
+        Order::Asc => ("ASC", ">"),
+        Order::Desc => ("DESC", "<"),
+    };
+    let mut page_query: QueryBuilder<Sqlite> = QueryBuilder::new("SELECT * FROM (");
+    push_results_query(&mut page_query, params);
+    page_query.push(") AS results");
+    if let Some(cursor) = cursor {
+        page_query.push(format!(
+            " WHERE ({}, results.frn) {} (",
+            sort.column(),
+            comparison
+        ));
+        if sort == Sort::Relevance {
+            let rank = cursor
+                .value
+                .parse::<f64>()
+                .map_err(|_| SearchError::InvalidCursor)?;
+            page_query.push_bind(rank);
+        } else {
+            page_query.push_bind(cursor.value);
+        }
+        page_query.push(", ");
+        page_query.push_bind(cursor.frn);
+        page_query.push(")");
+    }
+    page_query.push(format!(
+        " ORDER BY {} {}, results.frn {} LIMIT ",
+        sort.column(),
+        direction,
+        direction
+    ));
+    // One extra to tell whether there's another page
+    page_query.push_bind(limit + 1);
+
+    let mut results = page_query
+        .build_query_as::<SearchResult>()
+        .fetch_all(db)
+        .await?;
+    let next_cursor = if results.len() > limit as usize {
+        results.truncate(limit as usize);
+        results.last().map(|last| {
+            Cursor {
+                sort,
+                order,
+                value: sort.value(last),
+                frn: last.frn.clone(),
+            }
+            .encode()
+        })
+    } else {
+        None
+    };
+
+    Ok(SearchPage {
+        results,
+        total,
+        next_cursor,
+    })
+}
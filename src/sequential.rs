@@ -0,0 +1,316 @@
+//! Tracking where the FCC is in the sequential call sign system
+//!
+//! New licenses get the next unassigned call in their region and the group for their operator
+//! class. The dumps don't say where each sequence is, so this works it out from the licenses
+//! granted most recently. Renewals and vanity calls are ignored, since they can be anywhere in
+//! the sequence.
+
+use chrono::NaiveDate;
+use serde::Serialize;
+use sqlx::{FromRow, QueryBuilder, Sqlite, SqlitePool};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::callsign::{CallSign, Region};
+use crate::load::BIND_LIMIT;
+use crate::meta::{get_last_update, UpdateType};
+
+/// Second letters of a prefix that mark it as outside the contiguous US, which the contiguous
+/// sequence skips
+const REGION_LETTERS: &[u8] = b"HLP";
+
+/// How far back from the latest grant in a sequence to look for the furthest call issued, since
+/// calls aren't always granted in order
+const RECENT_WINDOW_DAYS: i64 = 30;
+
+/// Where a single region and group's sequence is, and what's likely to come next
+#[derive(Debug, Serialize)]
+pub struct Sequence {
+    pub region_code: i64,
+    pub group_code: String,
+    pub latest_call_sign: String,
+    pub latest_grant_date: NaiveDate,
+    pub next_call_signs: Vec<String>,
+}
+
+/// The furthest call issued in a single region and group's sequence
+#[derive(Debug, Clone)]
+struct Latest {
+    region_code: i64,
+    group_code: String,
+    call_sign: CallSign,
+    grant_date: NaiveDate,
+}
+
+/// Keeps the latest call in every sequence between requests, since finding them scans every
+/// license
+///
+/// The cache is keyed by the id of the database's last update, so it's recomputed once after each
+/// load, whether that was a daily applied in place or a whole new database swapped in.
+#[derive(Debug, Default)]
+pub struct SequenceCache {
+    cached: Mutex<Option<CachedLatest>>,
+}
+
+#[derive(Debug)]
+struct CachedLatest {
+    update_id: Option<u32>,
+    latest: Arc<Vec<Latest>>,
+}
+
+impl SequenceCache {
+    /// Does the same as [`get_sequences`], only scanning the licenses if the database has been
+    /// updated since the last call
+    pub async fn get_sequences(
+        &self,
+        db: &SqlitePool,
+        count: usize,
+    ) -> Result<Vec<Sequence>, sqlx::Error> {
+        let update_id = get_last_update(db, UpdateType::Any)
+            .await?
+            .map(|update| update.id);
+        let latest = {
+            // Held while scanning, so requests that come in meanwhile wait for it rather than all
+            // scanning at once
+            let mut cached = self.cached.lock().await;
+            match &*cached {
+                Some(cached) if cached.update_id == update_id => cached.latest.clone(),
+                _ => {
+                    let latest = Arc::new(find_latest(db).await?);
+                    *cached = Some(CachedLatest {
+                        update_id,
+                        latest: latest.clone(),
+                    });
+                    latest
+                }
+            }
+        };
+        predict_sequences(db, &latest, count).await
+    }
+}
+
+#[derive(FromRow)]
+struct RecentGrant {
+    region_code: i64,
+    group_code: String,
+    call_sign: String,
+    grant_date: NaiveDate,
+}
+
+/// Returns the call after this one in its sequence, or `None` at the end of its block
+///
+/// The suffix counts up like an odometer, and once it runs out a two letter prefix moves on to
+/// its next letter. Prefixes for Alaska, Hawaii and the other territories don't, since the second
+/// letter is what marks the region, and the contiguous sequence skips over those letters (H, L and
+/// P) rather than running into them. The `A` block ends at `AK`.
+///
+/// # Examples
+/// ```
+/// use artemis::callsign::CallSign;
+/// use artemis::sequential::next_in_sequence;
+///
+/// let next = |call: &str| {
+///     next_in_sequence(&call.parse::<CallSign>().unwrap()).map(|call| call.to_string())
+/// };
+/// assert_eq!(next("KJ4ABC").as_deref(), Some("KJ4ABD"));
+/// assert_eq!(next("KJ4AZZ").as_deref(), Some("KJ4BAA"));
+/// assert_eq!(next("KJ4ZZZ").as_deref(), Some("KK4AAA"));
+/// assert_eq!(next("KK4ZZZ").as_deref(), Some("KM4AAA"));
+/// assert_eq!(next("KO4ZZ").as_deref(), Some("KQ4AA"));
+/// assert_eq!(next("AJ4ZZ").as_deref(), Some("AK4AA"));
+/// assert_eq!(next("AK4ZZ"), None);
+/// assert_eq!(next("AL4ZZ"), None);
+/// assert_eq!(next("KL7ZZZ"), None);
+/// assert_eq!(next("N4ZZZ"), None);
+/// ```
+pub fn next_in_sequence(call_sign: &CallSign) -> Option<CallSign> {
+    let mut suffix = call_sign.suffix.clone().into_bytes();
+    if increment(&mut suffix) {
+        return Some(CallSign {
+            suffix: String::from_utf8(suffix).unwrap(),
+            ..call_sign.clone()
+        });
+    }
+
+    // The suffix wrapped around to all A's, so the prefix has to move on
+    let mut prefix = call_sign.prefix.clone().into_bytes();
+    let last_letter = if prefix[0] == b'A' { b'K' } else { b'Z' };
+    if prefix.len() != 2 || call_sign.region() != Region::Contiguous || prefix[1] >= last_letter {
+        return None;
+    }
+    prefix[1] += 1;
+    while REGION_LETTERS.contains(&prefix[1]) {
+        prefix[1] += 1;
+    }
+    Some(CallSign {
+        prefix: String::from_utf8(prefix).unwrap(),
+        digit: call_sign.digit,
+        suffix: String::from_utf8(suffix).unwrap(),
+    })
+}
+
+/// Adds one to a string of letters, returning false if it wrapped around
+fn increment(letters: &mut [u8]) -> bool {
+    for letter in letters.iter_mut().rev() {
+        if *letter < b'Z' {
+            *letter += 1;
+            return true;
+        }
+        *letter = b'A';
+    }
+    false
+}
+
+/// Orders calls by how far along the sequence they are, with longer formats coming later
+fn sequence_key(call_sign: &CallSign) -> (usize, String, String) {
+    (
+        call_sign.prefix.len() + call_sign.suffix.len(),
+        call_sign.prefix.clone(),
+        call_sign.suffix.clone(),
+    )
+}
+
+/// Returns the latest call issued in every region and group, with the next `count` calls
+/// predicted for each
+///
+/// This scans every license, so anything calling it often should go through a [`SequenceCache`].
+pub async fn get_sequences(db: &SqlitePool, count: usize) -> Result<Vec<Sequence>, sqlx::Error> {
+    let latest = find_latest(db).await?;
+    predict_sequences(db, &latest, count).await
+}
+
+/// Finds the latest call issued in every region and group
+async fn find_latest(db: &SqlitePool) -> Result<Vec<Latest>, sqlx::Error> {
+    let recent = sqlx::query_as::<_, RecentGrant>(
+        "WITH grants AS (
+            SELECT
+                amateurs.region_code,
+                amateurs.group_code,
+                amateurs.call_sign,
+                headers.grant_date
+            FROM amateurs
+            JOIN headers
+                ON headers.unique_system_identifier = amateurs.unique_system_identifier
+            WHERE
+                amateurs.vanity_call_sign_change != 'Y'
+                AND amateurs.region_code != ''
+                AND amateurs.group_code != ''
+                AND headers.grant_date IS NOT NULL
+                AND NOT EXISTS (
+                    SELECT 1
+                    FROM history
+                    WHERE
+                        history.unique_system_identifier = amateurs.unique_system_identifier
+                        AND history.code = 'LIREN'
+                )
+        )
+        SELECT grants.region_code, grants.group_code, grants.call_sign, grants.grant_date
+        FROM grants
+        JOIN (
+            SELECT region_code, group_code, MAX(grant_date) AS latest
+            FROM grants
+            GROUP BY region_code, group_code
+        ) AS latest
+            ON latest.region_code = grants.region_code
+            AND latest.group_code = grants.group_code
+        WHERE grants.grant_date >= date(latest.latest, ?)",
+    )
+    .bind(format!("-{} days", RECENT_WINDOW_DAYS))
+    .fetch_all(db)
+    .await?;
+
+    let mut latest: BTreeMap<(i64, String), (CallSign, NaiveDate)> = BTreeMap::new();
+    for grant in recent {
+        let call_sign = match grant.call_sign.parse::<CallSign>() {
+            Ok(call_sign) => call_sign,
+            Err(_) => continue,
+        };
+        let entry = latest
+            .entry((grant.region_code, grant.group_code))
+            .or_insert_with(|| (call_sign.clone(), grant.grant_date));
+        if sequence_key(&call_sign) > sequence_key(&entry.0) {
+            entry.0 = call_sign;
+        }
+        entry.1 = entry.1.max(grant.grant_date);
+    }
+
+    Ok(latest
+        .into_iter()
+        .map(
+            |((region_code, group_code), (call_sign, grant_date))| Latest {
+                region_code,
+                group_code,
+                call_sign,
+                grant_date,
+            },
+        )
+        .collect())
+}
+
+async fn predict_sequences(
+    db: &SqlitePool,
+    latest: &[Latest],
+    count: usize,
+) -> Result<Vec<Sequence>, sqlx::Error> {
+    let mut sequences = Vec::new();
+    for latest in latest {
+        sequences.push(Sequence {
+            region_code: latest.region_code,
+            group_code: latest.group_code.clone(),
+            latest_call_sign: latest.call_sign.to_string(),
+            latest_grant_date: latest.grant_date,
+            next_call_signs: predict(db, &latest.call_sign, count).await?,
+        });
+    }
+    Ok(sequences)
+}
+
+/// Returns the next `count` calls after this one that no license has, since the FCC skips those
+pub async fn predict(
+    db: &SqlitePool,
+    call_sign: &CallSign,
+    count: usize,
+) -> Result<Vec<String>, sqlx::Error> {
+    let mut predicted = Vec::new();
+    let mut current = call_sign.clone();
+    while predicted.len() < count {
+        // Check a few more than we need at a time, since there are usually some taken
+        let mut candidates = Vec::new();
+        while candidates.len() < (count * 2).min(BIND_LIMIT) {
+            match next_in_sequence(&current) {
+                Some(next) => {
+                    candidates.push(next.to_string());
+                    current = next;
+                }
+                None => break,
+            }
+        }
+        if candidates.is_empty() {
+            break;
+        }
+
+        let mut query_builder: QueryBuilder<Sqlite> =
+            QueryBuilder::new("SELECT call_sign FROM headers WHERE call_sign IN (");
+        let mut separated = query_builder.separated(", ");
+        for candidate in &candidates {
+            separated.push_bind(candidate);
+        }
+        query_builder.push(")");
+        let taken = query_builder
+            .build_query_as::<(String,)>()
+            .fetch_all(db)
+            .await?
+            .into_iter()
+            .map(|(call_sign,)| call_sign)
+            .collect::<HashSet<_>>();
+
+        predicted.extend(
+            candidates
+                .into_iter()
+                .filter(|candidate| !taken.contains(candidate))
+                .take(count - predicted.len()),
+        );
+    }
+    Ok(predicted)
+}
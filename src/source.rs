@@ -0,0 +1,320 @@
+//! Where the ULS dumps come from
+//!
+//! Normally that's the FCC's site, but an import can also run from a mirror of it, or a directory
+//! of dumps downloaded earlier, like on a host with no internet access or when replaying old dumps.
+
+use chrono::{DateTime, Utc, Weekday};
+use std::fmt;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::file::{download_file, DownloadError};
+use crate::load::SPECIAL_CONDITION_CODES_FILE_NAME;
+
+const FCC_BASE_URL: &str = "https://data.fcc.gov/download/pub/uls";
+const FCC_SPECIAL_CONDITIONS_URL: &str = "https://www.fcc.gov/file/20669/download";
+
+/// Dumps this size or smaller are empty, which is what the FCC publishes on days with no updates
+const EMPTY_DUMP_LEN: u64 = 212;
+
+/// One of the files an import needs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dump {
+    /// The complete weekly dump
+    Weekly,
+    /// The daily changes published on a given day
+    Daily(Weekday),
+    /// The text of the special condition codes, which isn't part of the ULS dumps
+    SpecialConditionCodes,
+}
+
+impl Dump {
+    /// Every daily dump, starting from Sunday like the FCC's week
+    pub const DAILY: [Dump; 7] = [
+        Dump::Daily(Weekday::Sun),
+        Dump::Daily(Weekday::Mon),
+        Dump::Daily(Weekday::Tue),
+        Dump::Daily(Weekday::Wed),
+        Dump::Daily(Weekday::Thu),
+        Dump::Daily(Weekday::Fri),
+        Dump::Daily(Weekday::Sat),
+    ];
+
+    /// The name the FCC gives the file, which is also what it's saved as
+    ///
+    /// # Examples
+    /// ```
+    /// use artemis::source::Dump;
+    /// use chrono::Weekday;
+    ///
+    /// assert_eq!(Dump::Weekly.file_name(), "l_amat.zip");
+    /// assert_eq!(Dump::Daily(Weekday::Wed).file_name(), "l_am_wed.zip");
+    /// ```
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Dump::Weekly => "l_amat.zip",
+            Dump::Daily(Weekday::Sun) => "l_am_sun.zip",
+            Dump::Daily(Weekday::Mon) => "l_am_mon.zip",
+            Dump::Daily(Weekday::Tue) => "l_am_tue.zip",
+            Dump::Daily(Weekday::Wed) => "l_am_wed.zip",
+            Dump::Daily(Weekday::Thu) => "l_am_thu.zip",
+            Dump::Daily(Weekday::Fri) => "l_am_fri.zip",
+            Dump::Daily(Weekday::Sat) => "l_am_sat.zip",
+            Dump::SpecialConditionCodes => SPECIAL_CONDITION_CODES_FILE_NAME,
+        }
+    }
+}
+
+impl fmt::Display for Dump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Dump::Weekly => write!(f, "weekly dump"),
+            Dump::Daily(day) => write!(f, "{} daily dump", day),
+            Dump::SpecialConditionCodes => write!(f, "special condition codes"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SourceError {
+    /// Checking a dump on a remote source failed
+    Request {
+        url: String,
+        source: Box<ureq::Error>,
+    },
+    /// Downloading a dump from a remote source failed
+    Download(DownloadError),
+    /// A dump in a local directory couldn't be read
+    Io { file: String, source: io::Error },
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceError::Request { url, source } => write!(f, "Error checking {url}: {source}"),
+            SourceError::Download(source) => source.fmt(f),
+            SourceError::Io { file, source } => write!(f, "Error accessing {file}: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for SourceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SourceError::Request { source, .. } => Some(source.as_ref()),
+            SourceError::Download(source) => source.source(),
+            SourceError::Io { source, .. } => Some(source),
+        }
+    }
+}
+
+impl From<DownloadError> for SourceError {
+    fn from(err: DownloadError) -> Self {
+        SourceError::Download(err)
+    }
+}
+
+/// Somewhere the dumps can be fetched from
+pub trait DumpSource: fmt::Debug {
+    /// Returns when the dump was last updated, or `None` if it's missing or empty
+    fn last_updated(&self, dump: Dump) -> Result<Option<DateTime<Utc>>, SourceError>;
+
+    /// Makes the dump available on disk, returning its path, or `None` if the source doesn't have
+    /// it
+    fn fetch(&self, dump: Dump) -> Result<Option<PathBuf>, SourceError>;
+}
+
+/// Returns the source for a location given on the command line
+///
+/// `fcc` is the FCC's site, an `http://` or `https://` URL is a mirror laid out like it, and
+/// anything else is a local directory, optionally as a `file://` URL. Remote dumps are downloaded
+/// into `download_dir`.
+///
+/// # Examples
+/// ```
+/// use artemis::source::from_location;
+/// use std::path::Path;
+///
+/// assert_eq!(
+///     format!("{:?}", from_location("file:///srv/uls", Path::new("."))),
+///     r#"LocalDirectory { path: "/srv/uls" }"#
+/// );
+/// ```
+pub fn from_location(location: &str, download_dir: &Path) -> Box<dyn DumpSource> {
+    if location == "fcc" {
+        Box::new(HttpSource::fcc(download_dir))
+    } else if location.starts_with("http://") || location.starts_with("https://") {
+        Box::new(HttpSource::mirror(location, download_dir))
+    } else {
+        let path = location.strip_prefix("file://").unwrap_or(location);
+        Box::new(LocalDirectory::new(path))
+    }
+}
+
+/// The FCC's site, or a mirror with the same layout
+///
+/// Dumps are downloaded into `download_dir` with [`download_file`], which skips any that are
+/// already there and up to date.
+#[derive(Debug, Clone)]
+pub struct HttpSource {
+    base_url: String,
+    special_conditions_url: String,
+    download_dir: PathBuf,
+}
+
+impl HttpSource {
+    pub fn fcc(download_dir: &Path) -> Self {
+        HttpSource {
+            base_url: FCC_BASE_URL.to_string(),
+            special_conditions_url: FCC_SPECIAL_CONDITIONS_URL.to_string(),
+            download_dir: download_dir.to_path_buf(),
+        }
+    }
+
+    /// A mirror of `https://data.fcc.gov/download/pub/uls`, with the weekly dump under
+    /// `complete/` and the daily ones under `daily/`
+    ///
+    /// The FCC serves the special condition codes from somewhere else entirely, so a mirror should
+    /// have them at the top level as `special_condition_codes.txt`.
+    pub fn mirror(base_url: &str, download_dir: &Path) -> Self {
+        let base_url = base_url.trim_end_matches('/');
+        HttpSource {
+            base_url: base_url.to_string(),
+            special_conditions_url: format!("{}/{}", base_url, SPECIAL_CONDITION_CODES_FILE_NAME),
+            download_dir: download_dir.to_path_buf(),
+        }
+    }
+
+    /// Returns the URL a dump is at
+    ///
+    /// # Examples
+    /// ```
+    /// use artemis::source::{Dump, HttpSource};
+    /// use chrono::Weekday;
+    /// use std::path::Path;
+    ///
+    /// let mirror = HttpSource::mirror("http://localhost:8000/uls/", Path::new("."));
+    /// assert_eq!(mirror.url(Dump::Weekly), "http://localhost:8000/uls/complete/l_amat.zip");
+    /// assert_eq!(
+    ///     mirror.url(Dump::Daily(Weekday::Sat)),
+    ///     "http://localhost:8000/uls/daily/l_am_sat.zip"
+    /// );
+    /// ```
+    pub fn url(&self, dump: Dump) -> String {
+        match dump {
+            Dump::Weekly => format!("{}/complete/{}", self.base_url, dump.file_name()),
+            Dump::Daily(_) => format!("{}/daily/{}", self.base_url, dump.file_name()),
+            Dump::SpecialConditionCodes => self.special_conditions_url.clone(),
+        }
+    }
+}
+
+impl DumpSource for HttpSource {
+    fn last_updated(&self, dump: Dump) -> Result<Option<DateTime<Utc>>, SourceError> {
+        let url = self.url(dump);
+        let resp = match ureq::head(&url).call() {
+            Ok(resp) => resp,
+            Err(ureq::Error::Status(404, _)) => return Ok(None),
+            Err(source) => {
+                return Err(SourceError::Request {
+                    url,
+                    source: Box::new(source),
+                })
+            }
+        };
+
+        let len = resp
+            .header("Content-Length")
+            .unwrap_or("0")
+            .parse()
+            .unwrap_or(0);
+        if len <= EMPTY_DUMP_LEN {
+            return Ok(None);
+        }
+
+        Ok(resp
+            .header("Last-Modified")
+            .and_then(|last_mod| DateTime::parse_from_rfc2822(last_mod).ok())
+            .map(DateTime::from))
+    }
+
+    fn fetch(&self, dump: Dump) -> Result<Option<PathBuf>, SourceError> {
+        let path = self.download_dir.join(dump.file_name());
+        match download_file(&self.url(dump), Some(&path)) {
+            Ok(_) => Ok(Some(path)),
+            Err(DownloadError::Status { status: 404, .. }) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// A directory of dumps downloaded earlier, all side by side under their usual names
+///
+/// A dump's modification time is used as when it was last updated, which is the FCC's
+/// Last-Modified time if it was saved by [`download_file`].
+///
+/// # Examples
+/// ```
+/// use artemis::source::{Dump, DumpSource, LocalDirectory};
+///
+/// let dir = std::env::temp_dir().join("artemis-local-directory-example");
+/// std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("l_amat.zip"), vec![0; 1024]).unwrap();
+///
+/// let source = LocalDirectory::new(&dir);
+/// assert!(source.last_updated(Dump::Weekly).unwrap().is_some());
+/// assert!(source.last_updated(Dump::SpecialConditionCodes).unwrap().is_none());
+/// assert_eq!(source.fetch(Dump::Weekly).unwrap(), Some(dir.join("l_amat.zip")));
+/// assert_eq!(source.fetch(Dump::SpecialConditionCodes).unwrap(), None);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct LocalDirectory {
+    path: PathBuf,
+}
+
+impl LocalDirectory {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        LocalDirectory {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl DumpSource for LocalDirectory {
+    fn last_updated(&self, dump: Dump) -> Result<Option<DateTime<Utc>>, SourceError> {
+        let path = self.path.join(dump.file_name());
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(source) => {
+                return Err(SourceError::Io {
+                    file: path.display().to_string(),
+                    source,
+                })
+            }
+        };
+        if metadata.len() <= EMPTY_DUMP_LEN {
+            return Ok(None);
+        }
+
+        let modified = metadata.modified().map_err(|source| SourceError::Io {
+            file: path.display().to_string(),
+            source,
+        })?;
+        Ok(Some(modified.into()))
+    }
+
+    fn fetch(&self, dump: Dump) -> Result<Option<PathBuf>, SourceError> {
+        let path = self.path.join(dump.file_name());
+        match fs::metadata(&path) {
+            Ok(_) => Ok(Some(path)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(source) => Err(SourceError::Io {
+                file: path.display().to_string(),
+                source,
+            }),
+        }
+    }
+}
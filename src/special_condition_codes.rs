@@ -0,0 +1,166 @@
+//! Parser for the special condition codes file at <https://www.fcc.gov/file/20669/download>
+//!
+//! Unlike the ULS dumps, this file has no header or documentation at all. Each code looks like
+//!
+//! ```text
+//! code|service|text1|text2|text3|text4|text5|flag
+//! ```
+//!
+//! where the condition's text is split across the five text fields, and the flag is either
+//! empty or one of `R`, `P`, or `T`. Newlines in the middle of a code can be either `\n` or
+//! `\r\n`, the same as the ones between codes, so codes are put back together by counting fields.
+
+use std::fmt;
+
+/// The number of fields in a single code
+const FIELD_COUNT: usize = 8;
+
+/// A single special condition, with its text still in the segments the FCC split it into
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpecialConditionCode {
+    pub code: i32,
+    pub service: String,
+    pub text: [String; 5],
+    /// The trailing `R`, `P`, or `T`, whatever it means
+    pub flag: Option<String>,
+}
+
+impl SpecialConditionCode {
+    /// The full text of the condition, with the segments joined back together
+    ///
+    /// # Examples
+    /// ```
+    /// # use artemis::special_condition_codes::parse_record;
+    /// let code = parse_record("999|AM|Operation is|subject to| the rules.|||").unwrap();
+    /// assert_eq!(code.description(), "Operation is subject to the rules.");
+    /// ```
+    pub fn description(&self) -> String {
+        self.text
+            .iter()
+            .map(|segment| segment.trim())
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The record didn't have exactly [`FIELD_COUNT`] fields
+    FieldCount(usize),
+    /// The code wasn't a number
+    Code(String),
+    /// The flag wasn't empty, `R`, `P`, or `T`
+    Flag(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::FieldCount(count) => {
+                write!(f, "expected {} fields, found {}", FIELD_COUNT, count)
+            }
+            ParseError::Code(code) => write!(f, "invalid code {:?}", code),
+            ParseError::Flag(flag) => write!(f, "invalid flag {:?}", flag),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Splits the raw file into one string per code, joining codes that were broken across lines
+///
+/// Lines are joined with a space until there are enough fields for a whole code and the last
+/// field is a valid flag. Anything left over at the end of the file is returned as-is, so it can
+/// be reported by [`parse_record`].
+///
+/// # Examples
+/// ```
+/// # use artemis::special_condition_codes::split_records;
+/// let raw = "1|AM|first\nline|||||\r\n2|AM|second|line||||R\r\n";
+/// assert_eq!(
+///     split_records(raw),
+///     vec!["1|AM|first line|||||", "2|AM|second|line||||R"]
+/// );
+///
+/// // A CRLF in the middle of a code is no different
+/// let raw = "3|AM|split\r\nhere|||||T\r\n";
+/// assert_eq!(split_records(raw), vec!["3|AM|split here|||||T"]);
+/// ```
+pub fn split_records(raw: &str) -> Vec<String> {
+    let mut records = Vec::new();
+    let mut current = String::new();
+
+    for line in raw.lines() {
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(line.trim_end_matches('\r'));
+
+        if is_complete(&current) {
+            records.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.trim().is_empty() {
+        records.push(current);
+    }
+
+    records
+}
+
+fn is_complete(record: &str) -> bool {
+    let fields = record.split('|').collect::<Vec<_>>();
+    match fields.len() {
+        n if n < FIELD_COUNT => false,
+        n if n == FIELD_COUNT => matches!(fields[FIELD_COUNT - 1], "" | "R" | "P" | "T"),
+        // Too many fields to ever be valid, so stop here and let it be rejected
+        _ => true,
+    }
+}
+
+/// Parses a single code, as returned by [`split_records`]
+///
+/// # Examples
+/// ```
+/// # use artemis::special_condition_codes::{parse_record, ParseError};
+/// let code = parse_record("4020|AM|The licensee|may not|operate.|||P").unwrap();
+/// assert_eq!(code.code, 4020);
+/// assert_eq!(code.service, "AM");
+/// assert_eq!(code.text[1], "may not");
+/// assert_eq!(code.flag.as_deref(), Some("P"));
+///
+/// assert_eq!(parse_record("4020|AM|short"), Err(ParseError::FieldCount(3)));
+/// assert_eq!(
+///     parse_record("abc|AM||||||"),
+///     Err(ParseError::Code("abc".to_string()))
+/// );
+/// ```
+pub fn parse_record(record: &str) -> Result<SpecialConditionCode, ParseError> {
+    let fields = record.split('|').collect::<Vec<_>>();
+    if fields.len() != FIELD_COUNT {
+        return Err(ParseError::FieldCount(fields.len()));
+    }
+
+    let code = fields[0]
+        .trim()
+        .parse()
+        .map_err(|_| ParseError::Code(fields[0].to_string()))?;
+    let flag = match fields[7].trim() {
+        "" => None,
+        flag @ ("R" | "P" | "T") => Some(flag.to_string()),
+        flag => return Err(ParseError::Flag(flag.to_string())),
+    };
+
+    Ok(SpecialConditionCode {
+        code,
+        service: fields[1].to_string(),
+        text: [
+            fields[2].to_string(),
+            fields[3].to_string(),
+            fields[4].to_string(),
+            fields[5].to_string(),
+            fields[6].to_string(),
+        ],
+        flag,
+    })
+}
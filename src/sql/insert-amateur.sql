@@ -13,7 +13,7 @@ INSERT OR REPLACE INTO amateurs (
         ve_signature,
         systematic_call_sign_change,
         vanity_call_sign_change,
-        vainty_relationship,
+        vanity_relationship,
         previous_call_sign,
         previous_operator_class,
         trustee_name
@@ -28,5 +28,6 @@ INSERT OR REPLACE INTO entities (
         status_date,
         lic_category_code,
         linked_license_id,
-        linked_callsign
+        linked_callsign,
+        last_name_soundex
     )
\ No newline at end of file
@@ -0,0 +1,7 @@
+INSERT INTO rejected_records (
+        update_id,
+        file_name,
+        line_number,
+        raw_line,
+        error
+    )
\ No newline at end of file
@@ -1 +1,10 @@
-INSERT OR REPLACE INTO special_condition_codes (code, service, description, unknown)
\ No newline at end of file
+INSERT OR REPLACE INTO special_condition_codes (
+        code,
+        service,
+        text_1,
+        text_2,
+        text_3,
+        text_4,
+        text_5,
+        flag
+    )
\ No newline at end of file
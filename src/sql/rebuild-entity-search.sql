@@ -0,0 +1,22 @@
+-- Migration 2 runs before last_name_soundex exists, so this index is put back here rather than in
+-- 02-create-indexes.sql
+CREATE INDEX IF NOT EXISTS idx_entities_last_name_soundex ON entities (last_name_soundex);
+
+INSERT INTO entities_fts (entities_fts) VALUES ('rebuild');
+
+-- The same triggers as 05-create-entity-search.sql, which suspend-entity-search.sql dropped
+CREATE TRIGGER IF NOT EXISTS entities_fts_insert AFTER INSERT ON entities BEGIN
+  INSERT INTO entities_fts (rowid, entity_name, first_name, last_name, city, attention_line)
+    VALUES (new.unique_system_identifier, new.entity_name, new.first_name, new.last_name, new.city, new.attention_line);
+END;
+CREATE TRIGGER IF NOT EXISTS entities_fts_delete AFTER DELETE ON entities BEGIN
+  INSERT INTO entities_fts (entities_fts, rowid, entity_name, first_name, last_name, city, attention_line)
+    VALUES ('delete', old.unique_system_identifier, old.entity_name, old.first_name, old.last_name, old.city, old.attention_line);
+END;
+CREATE TRIGGER IF NOT EXISTS entities_fts_update
+  AFTER UPDATE OF entity_name, first_name, last_name, city, attention_line ON entities BEGIN
+  INSERT INTO entities_fts (entities_fts, rowid, entity_name, first_name, last_name, city, attention_line)
+    VALUES ('delete', old.unique_system_identifier, old.entity_name, old.first_name, old.last_name, old.city, old.attention_line);
+  INSERT INTO entities_fts (rowid, entity_name, first_name, last_name, city, attention_line)
+    VALUES (new.unique_system_identifier, new.entity_name, new.first_name, new.last_name, new.city, new.attention_line);
+END;
@@ -0,0 +1,7 @@
+-- Weekly loads replace every entity, so instead of keeping entities_fts in sync a row at a time the
+-- triggers come out, and rebuild-entity-search.sql indexes everything at once after the load.
+-- With the triggers gone nothing would remove the old rows from the index, so it's emptied here.
+DROP TRIGGER IF EXISTS entities_fts_insert;
+DROP TRIGGER IF EXISTS entities_fts_delete;
+DROP TRIGGER IF EXISTS entities_fts_update;
+INSERT INTO entities_fts (entities_fts) VALUES ('delete-all');
@@ -1,10 +1,26 @@
 #![allow(dead_code, non_snake_case)]
 
+use crate::codes::{ApplicantType, EntityType, LicenseStatus, OperatorClass};
 use crate::fcc_date;
+use crate::load::UlsRecord;
+use crate::search::soundex;
 use chrono::NaiveDate;
 use chrono::{DateTime, Utc};
 use serde::Deserialize;
-use sqlx::FromRow;
+use sqlx::query_builder::Separated;
+use sqlx::{FromRow, Sqlite};
+
+/// The tables every [`UlsRecord`] is loaded into, which all have a `unique_system_identifier`
+pub const RECORD_TABLES: [&str; 8] = [
+    Amateur::TABLE,
+    Comment::TABLE,
+    Entity::TABLE,
+    Header::TABLE,
+    History::TABLE,
+    LicenseAttachment::TABLE,
+    SpecialCondition::TABLE,
+    SpecialConditionFreeForm::TABLE,
+];
 
 #[derive(Debug, Deserialize, FromRow)]
 pub struct Update {
@@ -21,7 +37,7 @@ pub struct Amateur<'a> {
     pub UlsFileNumber: &'a str,
     pub EBFNumber: &'a str,
     pub CallSign: &'a str,
-    pub OperatorClass: &'a str,
+    pub OperatorClass: Option<OperatorClass>,
     pub GroupCode: &'a str,
     pub RegionCode: &'a str,
     pub TrusteeCallSign: &'a str,
@@ -32,14 +48,46 @@ pub struct Amateur<'a> {
     pub VanityCallSignChange: &'a str,
     pub VanityRelationship: &'a str,
     pub PreviousCallSign: &'a str,
-    pub PreviousOperatorClass: &'a str,
+    pub PreviousOperatorClass: Option<OperatorClass>,
     pub TrusteeName: &'a str,
 }
 
+impl UlsRecord for Amateur<'_> {
+    const FILE_NAME: &'static str = "AM.dat";
+    const TABLE: &'static str = "amateurs";
+    const INSERT_SQL: &'static str = include_str!("sql/insert-amateur.sql");
+    const COLUMNS: usize = 18;
+    const FIELD_COUNT: usize = 18;
+
+    type Record<'a> = Amateur<'a>;
+
+    fn bind<'a>(amateur: Amateur<'a>, mut builder: Separated<'_, 'a, Sqlite, &'static str>) {
+        builder
+            .push_bind(amateur.RecordType)
+            .push_bind(amateur.UniqueSystemIdentifier)
+            .push_bind(amateur.UlsFileNumber)
+            .push_bind(amateur.EBFNumber)
+            .push_bind(amateur.CallSign)
+            .push_bind(amateur.OperatorClass)
+            .push_bind(amateur.GroupCode)
+            .push_bind(amateur.RegionCode)
+            .push_bind(amateur.TrusteeCallSign)
+            .push_bind(amateur.TrusteeIndicator)
+            .push_bind(amateur.PhysicianCertification)
+            .push_bind(amateur.VESignature)
+            .push_bind(amateur.SystematicCallSignChange)
+            .push_bind(amateur.VanityCallSignChange)
+            .push_bind(amateur.VanityRelationship)
+            .push_bind(amateur.PreviousCallSign)
+            .push_bind(amateur.PreviousOperatorClass)
+            .push_bind(amateur.TrusteeName);
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Comment<'a> {
     pub RecordType: &'a str,
-    pub UniqueSystemIdentifier: &'a str,
+    pub UniqueSystemIdentifier: u32,
     pub UlsFileNumber: &'a str,
     pub CallSign: &'a str,
     #[serde(with = "fcc_date")]
@@ -50,6 +98,28 @@ pub struct Comment<'a> {
     pub StatusDate: Option<NaiveDate>,
 }
 
+impl UlsRecord for Comment<'_> {
+    const FILE_NAME: &'static str = "CO.dat";
+    const TABLE: &'static str = "comments";
+    const INSERT_SQL: &'static str = include_str!("sql/insert-comment.sql");
+    const COLUMNS: usize = 8;
+    const FIELD_COUNT: usize = 8;
+
+    type Record<'a> = Comment<'a>;
+
+    fn bind<'a>(comment: Comment<'a>, mut builder: Separated<'_, 'a, Sqlite, &'static str>) {
+        builder
+            .push_bind(comment.RecordType)
+            .push_bind(comment.UniqueSystemIdentifier)
+            .push_bind(comment.UlsFileNumber)
+            .push_bind(comment.CallSign)
+            .push_bind(comment.CommentDate)
+            .push_bind(comment.Description)
+            .push_bind(comment.StatusCode)
+            .push_bind(comment.StatusDate);
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct Entity<'a> {
     pub RecordType: &'a str,
@@ -57,7 +127,7 @@ pub struct Entity<'a> {
     pub UlsFileNumber: &'a str,
     pub EBFNumber: &'a str,
     pub CallSign: &'a str,
-    pub EntityType: &'a str,
+    pub EntityType: EntityType,
     pub LicenseeId: &'a str,
     pub EntityName: &'a str,
     pub FirstName: &'a str,
@@ -75,7 +145,7 @@ pub struct Entity<'a> {
     pub AttentionLine: &'a str,
     pub SGIN: &'a str,
     pub FRN: &'a str,
-    pub ApplicantTypeCode: &'a str,
+    pub ApplicantTypeCode: Option<ApplicantType>,
     pub ApplicantTypeCodeOther: &'a str,
     pub StatusCode: &'a str,
     #[serde(with = "fcc_date")]
@@ -85,6 +155,51 @@ pub struct Entity<'a> {
     pub LinkedCallsign: &'a str,
 }
 
+impl UlsRecord for Entity<'_> {
+    const FILE_NAME: &'static str = "EN.dat";
+    const TABLE: &'static str = "entities";
+    const INSERT_SQL: &'static str = include_str!("sql/insert-entity.sql");
+    const COLUMNS: usize = 31;
+    const FIELD_COUNT: usize = 30;
+
+    type Record<'a> = Entity<'a>;
+
+    fn bind<'a>(entity: Entity<'a>, mut builder: Separated<'_, 'a, Sqlite, &'static str>) {
+        builder
+            .push_bind(entity.RecordType)
+            .push_bind(entity.UniqueSystemIdentifier)
+            .push_bind(entity.UlsFileNumber)
+            .push_bind(entity.EBFNumber)
+            .push_bind(entity.CallSign)
+            .push_bind(entity.EntityType)
+            .push_bind(entity.LicenseeId)
+            .push_bind(entity.EntityName)
+            .push_bind(entity.FirstName)
+            .push_bind(entity.MiddleInitial)
+            .push_bind(entity.LastName)
+            .push_bind(entity.Suffix)
+            .push_bind(entity.Phone)
+            .push_bind(entity.Fax)
+            .push_bind(entity.Email)
+            .push_bind(entity.StreetAddress)
+            .push_bind(entity.City)
+            .push_bind(entity.State)
+            .push_bind(entity.ZipCode)
+            .push_bind(entity.POBox)
+            .push_bind(entity.AttentionLine)
+            .push_bind(entity.SGIN)
+            .push_bind(entity.FRN)
+            .push_bind(entity.ApplicantTypeCode)
+            .push_bind(entity.ApplicantTypeCodeOther)
+            .push_bind(entity.StatusCode)
+            .push_bind(entity.StatusDate)
+            .push_bind(entity.ThreePointSevenGhzLicenseType)
+            .push_bind(entity.LinkedUniqueSystemIdentifier)
+            .push_bind(entity.LinkedCallsign)
+            .push_bind(soundex(entity.LastName));
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct Header<'a> {
     pub RecordType: &'a str,
@@ -92,7 +207,7 @@ pub struct Header<'a> {
     pub UlsFileNumber: &'a str,
     pub EBFNumber: &'a str,
     pub CallSign: &'a str,
-    pub LicenseStatus: &'a str,
+    pub LicenseStatus: LicenseStatus,
     pub RadioServiceCode: &'a str,
     #[serde(with = "fcc_date")]
     pub GrantDate: Option<NaiveDate>,
@@ -153,10 +268,83 @@ pub struct Header<'a> {
     pub PaymentCertification900Mhz: &'a str,
 }
 
+impl UlsRecord for Header<'_> {
+    const FILE_NAME: &'static str = "HD.dat";
+    const TABLE: &'static str = "headers";
+    const INSERT_SQL: &'static str = include_str!("sql/insert-header.sql");
+    const COLUMNS: usize = 59;
+    const FIELD_COUNT: usize = 59;
+
+    type Record<'a> = Header<'a>;
+
+    fn bind<'a>(header: Header<'a>, mut builder: Separated<'_, 'a, Sqlite, &'static str>) {
+        builder
+            .push_bind(header.RecordType)
+            .push_bind(header.UniqueSystemIdentifier)
+            .push_bind(header.UlsFileNumber)
+            .push_bind(header.EBFNumber)
+            .push_bind(header.CallSign)
+            .push_bind(header.LicenseStatus)
+            .push_bind(header.RadioServiceCode)
+            .push_bind(header.GrantDate)
+            .push_bind(header.ExpiredDate)
+            .push_bind(header.CancellationDate)
+            .push_bind(header.EligibilityRuleNumber)
+            .push_bind(header.Reserved)
+            .push_bind(header.Alien)
+            .push_bind(header.AlienGovernment)
+            .push_bind(header.AlienCorporation)
+            .push_bind(header.AlienOfficers)
+            .push_bind(header.AlienControl)
+            .push_bind(header.Revoked)
+            .push_bind(header.Convicted)
+            .push_bind(header.Adjudged)
+            .push_bind(header.Reserved2)
+            .push_bind(header.CommonCarrier)
+            .push_bind(header.NonCommonCarrier)
+            .push_bind(header.PrivateComm)
+            .push_bind(header.Fixed)
+            .push_bind(header.Mobile)
+            .push_bind(header.Radiolocation)
+            .push_bind(header.Sattelite)
+            .push_bind(header.DevelopmentalOrSta)
+            .push_bind(header.InterconnectedService)
+            .push_bind(header.CertifierFirstName)
+            .push_bind(header.CertifierMiddleInitial)
+            .push_bind(header.CertifierLastName)
+            .push_bind(header.CertifierSuffix)
+            .push_bind(header.CertifierTitle)
+            .push_bind(header.Female)
+            .push_bind(header.BlackOrAfricanAmerican)
+            .push_bind(header.NativeAmerican)
+            .push_bind(header.Hawaiian)
+            .push_bind(header.Asian)
+            .push_bind(header.White)
+            .push_bind(header.Hispanic)
+            .push_bind(header.EffectiveDate)
+            .push_bind(header.LastActionDate)
+            .push_bind(header.AuctionId)
+            .push_bind(header.BroadcastServicesRegulatoryStatus)
+            .push_bind(header.BandManagerRegulatoryStatus)
+            .push_bind(header.BroadcastServicesTypeOfRadioService)
+            .push_bind(header.AlienRuling)
+            .push_bind(header.LicenseeNameChange)
+            .push_bind(header.WhitespaceIndicator)
+            .push_bind(header.OperationRequirementChoice)
+            .push_bind(header.OperationRequirementAnswer)
+            .push_bind(header.DiscontinuationOfService)
+            .push_bind(header.RegulatoryCompliance)
+            .push_bind(header.EligibilityCertification900Mhz)
+            .push_bind(header.TransitionPlanCertification900Mhz)
+            .push_bind(header.ReturnSpectrumCertification900Mhz)
+            .push_bind(header.PaymentCertification900Mhz);
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct History<'a> {
     pub RecordType: &'a str,
-    pub UniqueSystemIdentifier: &'a str,
+    pub UniqueSystemIdentifier: u32,
     pub UlsFileNumber: &'a str,
     pub CallSign: &'a str,
     #[serde(with = "fcc_date")]
@@ -164,6 +352,26 @@ pub struct History<'a> {
     pub Code: &'a str,
 }
 
+impl UlsRecord for History<'_> {
+    const FILE_NAME: &'static str = "HS.dat";
+    const TABLE: &'static str = "history";
+    const INSERT_SQL: &'static str = include_str!("sql/insert-history.sql");
+    const COLUMNS: usize = 6;
+    const FIELD_COUNT: usize = 6;
+
+    type Record<'a> = History<'a>;
+
+    fn bind<'a>(history: History<'a>, mut builder: Separated<'_, 'a, Sqlite, &'static str>) {
+        builder
+            .push_bind(history.RecordType)
+            .push_bind(history.UniqueSystemIdentifier)
+            .push_bind(history.UlsFileNumber)
+            .push_bind(history.CallSign)
+            .push_bind(history.LogDate)
+            .push_bind(history.Code);
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct LicenseAttachment<'a> {
     pub RecordType: &'a str,
@@ -176,6 +384,31 @@ pub struct LicenseAttachment<'a> {
     pub AttachmentFileName: &'a str,
     pub ActionPerformed: &'a str,
 }
+
+impl UlsRecord for LicenseAttachment<'_> {
+    const FILE_NAME: &'static str = "LA.dat";
+    const TABLE: &'static str = "license_attachments";
+    const INSERT_SQL: &'static str = include_str!("sql/insert-license-attachment.sql");
+    const COLUMNS: usize = 8;
+    const FIELD_COUNT: usize = 8;
+
+    type Record<'a> = LicenseAttachment<'a>;
+
+    fn bind<'a>(
+        attachment: LicenseAttachment<'a>,
+        mut builder: Separated<'_, 'a, Sqlite, &'static str>,
+    ) {
+        builder
+            .push_bind(attachment.RecordType)
+            .push_bind(attachment.UniqueSystemIdentifier)
+            .push_bind(attachment.CallSign)
+            .push_bind(attachment.AttachmentCode)
+            .push_bind(attachment.AttachmentDescription)
+            .push_bind(attachment.AttachmentDate)
+            .push_bind(attachment.AttachmentFileName)
+            .push_bind(attachment.ActionPerformed);
+    }
+}
 #[allow(dead_code, non_snake_case)]
 #[derive(Deserialize, Debug)]
 pub struct SpecialCondition<'a> {
@@ -191,6 +424,32 @@ pub struct SpecialCondition<'a> {
     pub StatusDate: Option<NaiveDate>,
 }
 
+impl UlsRecord for SpecialCondition<'_> {
+    const FILE_NAME: &'static str = "SC.dat";
+    const TABLE: &'static str = "special_conditions";
+    const INSERT_SQL: &'static str = include_str!("sql/insert-special-condition.sql");
+    const COLUMNS: usize = 9;
+    const FIELD_COUNT: usize = 9;
+
+    type Record<'a> = SpecialCondition<'a>;
+
+    fn bind<'a>(
+        condition: SpecialCondition<'a>,
+        mut builder: Separated<'_, 'a, Sqlite, &'static str>,
+    ) {
+        builder
+            .push_bind(condition.RecordType)
+            .push_bind(condition.UniqueSystemIdentifier)
+            .push_bind(condition.UlsFileNumber)
+            .push_bind(condition.EBFNumber)
+            .push_bind(condition.CallSign)
+            .push_bind(condition.SpecialConditionType)
+            .push_bind(condition.SpecialConditionCode)
+            .push_bind(condition.StatusCode)
+            .push_bind(condition.StatusDate);
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct SpecialConditionFreeForm<'a> {
     pub RecordType: &'a str,
@@ -206,3 +465,31 @@ pub struct SpecialConditionFreeForm<'a> {
     #[serde(with = "fcc_date")]
     pub StatusDate: Option<NaiveDate>,
 }
+
+impl UlsRecord for SpecialConditionFreeForm<'_> {
+    const FILE_NAME: &'static str = "SF.dat";
+    const TABLE: &'static str = "special_conditions_free_form";
+    const INSERT_SQL: &'static str = include_str!("sql/insert-special-condition-free-form.sql");
+    const COLUMNS: usize = 11;
+    const FIELD_COUNT: usize = 11;
+
+    type Record<'a> = SpecialConditionFreeForm<'a>;
+
+    fn bind<'a>(
+        condition: SpecialConditionFreeForm<'a>,
+        mut builder: Separated<'_, 'a, Sqlite, &'static str>,
+    ) {
+        builder
+            .push_bind(condition.RecordType)
+            .push_bind(condition.UniqueSystemIdentifier)
+            .push_bind(condition.UlsFileNumber)
+            .push_bind(condition.EBFNumber)
+            .push_bind(condition.CallSign)
+            .push_bind(condition.LicenseFreeFormType)
+            .push_bind(condition.UniqueLicenseFreeFormIdentifier)
+            .push_bind(condition.SequenceNumber)
+            .push_bind(condition.LicenseFreeFormCondition)
+            .push_bind(condition.StatusCode)
+            .push_bind(condition.StatusDate);
+    }
+}
@@ -0,0 +1,306 @@
+//! Checking whether a call sign can be requested as a vanity call
+//!
+//! A call can't be requested while any license holds it, or for two years after the last license
+//! to hold it expired or was cancelled. On top of that, applicants can only request calls from
+//! the groups their operator class is eligible for.
+
+use chrono::{Days, Months, NaiveDate};
+use itertools::Itertools;
+use serde::Serialize;
+use sqlx::{FromRow, QueryBuilder, Sqlite, SqlitePool};
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::callsign::{CallSign, Group};
+use crate::codes::{LicenseStatus, OperatorClass};
+use crate::load::BIND_LIMIT;
+
+/// How long a call is held back after the license holding it ends
+const GRACE_PERIOD: Months = Months::new(24);
+
+/// Why a call sign is or isn't available
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    Available,
+    /// The call isn't in any sequential call sign group, such as 1x1 special event calls
+    NotAssignable,
+    /// A license holding the call is still active
+    Active,
+    /// The last license holding the call ended less than two years ago
+    GracePeriod,
+    /// The call's group isn't one the applicant's operator class can request
+    ClassNotEligible,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Availability {
+    pub call_sign: String,
+    pub available: bool,
+    pub reason: Reason,
+    pub group: Option<Group>,
+    /// The first day the call can be requested, if it isn't already
+    pub available_date: Option<NaiveDate>,
+}
+
+/// The dates that matter from a single license that held the call
+#[derive(Debug, FromRow)]
+pub struct LicenseDates {
+    pub license_status: LicenseStatus,
+    pub expired_date: Option<NaiveDate>,
+    pub cancellation_date: Option<NaiveDate>,
+    /// The last expiration or cancellation in `history`, for licenses missing both dates
+    pub history_date: Option<NaiveDate>,
+}
+
+impl LicenseDates {
+    /// The day the license stopped holding the call, or for an active one, the day it will
+    fn end_date(&self) -> Option<NaiveDate> {
+        match (self.cancellation_date, self.expired_date) {
+            (Some(cancelled), Some(expired)) => Some(cancelled.min(expired)),
+            (Some(date), None) | (None, Some(date)) => Some(date),
+            (None, None) => self.history_date,
+        }
+    }
+}
+
+/// Returns the groups an operator class can request vanity calls from
+///
+/// # Examples
+/// ```
+/// use artemis::callsign::Group;
+/// use artemis::codes::OperatorClass;
+/// use artemis::vanity::eligible_groups;
+///
+/// assert_eq!(eligible_groups(&OperatorClass::Extra).len(), 4);
+/// assert_eq!(eligible_groups(&OperatorClass::General), &[Group::C, Group::D]);
+/// assert!(eligible_groups(&OperatorClass::Unknown("".to_string())).is_empty());
+/// ```
+pub fn eligible_groups(operator_class: &OperatorClass) -> &'static [Group] {
+    match operator_class {
+        OperatorClass::Extra => &[Group::A, Group::B, Group::C, Group::D],
+        OperatorClass::Advanced => &[Group::B, Group::C, Group::D],
+        OperatorClass::General | OperatorClass::TechnicianPlus | OperatorClass::Technician => {
+            &[Group::C, Group::D]
+        }
+        OperatorClass::Novice => &[Group::D],
+        OperatorClass::Unknown(_) => &[],
+    }
+}
+
+/// Decides whether a call is available on `today`, given every license that has held it
+///
+/// # Examples
+/// ```
+/// use artemis::callsign::CallSign;
+/// use artemis::codes::{LicenseStatus, OperatorClass};
+/// use artemis::vanity::{evaluate, LicenseDates, Reason};
+/// use chrono::NaiveDate;
+///
+/// let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+/// let call: CallSign = "W1XY".parse().unwrap();
+/// let licenses = [LicenseDates {
+///     license_status: LicenseStatus::Expired,
+///     expired_date: Some(date(2022, 3, 1)),
+///     cancellation_date: None,
+///     history_date: None,
+/// }];
+///
+/// let early = evaluate(&call, &licenses, None, date(2023, 6, 1));
+/// assert_eq!(early.reason, Reason::GracePeriod);
+/// assert_eq!(early.available_date, Some(date(2024, 3, 2)));
+///
+/// let later = evaluate(&call, &licenses, None, date(2024, 3, 2));
+/// assert_eq!(later.reason, Reason::Available);
+///
+/// let general = evaluate(&call, &licenses, Some(&OperatorClass::General), date(2024, 3, 2));
+/// assert_eq!(general.reason, Reason::ClassNotEligible);
+/// ```
+pub fn evaluate(
+    call_sign: &CallSign,
+    licenses: &[LicenseDates],
+    operator_class: Option<&OperatorClass>,
+    today: NaiveDate,
+) -> Availability {
+    let group = call_sign.group();
+    // Calls become available the day after the grace period ends
+    let available_date = licenses
+        .iter()
+        .filter_map(LicenseDates::end_date)
+        .max()
+        .and_then(|end| end.checked_add_months(GRACE_PERIOD))
+        .and_then(|end| end.checked_add_days(Days::new(1)))
+        .filter(|date| *date > today);
+
+    let active = licenses
+        .iter()
+        .any(|license| license.license_status == LicenseStatus::Active);
+    let reason = match group {
+        None => Reason::NotAssignable,
+        Some(_) if active => Reason::Active,
+        Some(_) if available_date.is_some() => Reason::GracePeriod,
+        Some(group)
+            if operator_class.is_some_and(|class| !eligible_groups(class).contains(&group)) =>
+        {
+            Reason::ClassNotEligible
+        }
+        Some(_) => Reason::Available,
+    };
+
+    Availability {
+        call_sign: call_sign.to_string(),
+        available: reason == Reason::Available,
+        reason,
+        group,
+        available_date,
+    }
+}
+
+/// Checks whether a call is available today, see [`evaluate`]
+pub async fn check(
+    db: &SqlitePool,
+    call_sign: &CallSign,
+    operator_class: Option<&OperatorClass>,
+) -> Result<Availability, sqlx::Error> {
+    let mut availability = check_batch(db, std::slice::from_ref(call_sign), operator_class).await?;
+    Ok(availability.remove(0))
+}
+
+#[derive(FromRow)]
+struct CandidateRow {
+    call_sign: String,
+    #[sqlx(flatten)]
+    dates: LicenseDates,
+}
+
+/// Checks a batch of calls with a single query, returning their availability in the same order
+///
+/// The batch can't be larger than [`BATCH_SIZE`].
+pub async fn check_batch(
+    db: &SqlitePool,
+    call_signs: &[CallSign],
+    operator_class: Option<&OperatorClass>,
+) -> Result<Vec<Availability>, sqlx::Error> {
+    let mut query_builder: QueryBuilder<Sqlite> =
+        QueryBuilder::new("WITH candidates (call_sign) AS (");
+    query_builder.push_values(call_signs, |mut builder, call_sign| {
+        builder.push_bind(call_sign.to_string());
+    });
+    query_builder.push(
+        ")
+        SELECT
+            candidates.call_sign,
+            headers.license_status,
+            headers.expired_date,
+            headers.cancellation_date,
+            (
+                SELECT MAX(log_date)
+                FROM history
+                WHERE
+                    history.unique_system_identifier = headers.unique_system_identifier
+                    AND history.code IN ('LIEXP', 'LICAN')
+            ) AS history_date
+        FROM candidates
+        JOIN headers ON headers.call_sign = candidates.call_sign",
+    );
+
+    let mut licenses: HashMap<String, Vec<LicenseDates>> = HashMap::new();
+    for row in query_builder
+        .build_query_as::<CandidateRow>()
+        .fetch_all(db)
+        .await?
+    {
+        licenses.entry(row.call_sign).or_default().push(row.dates);
+    }
+
+    let today = chrono::Local::now().date_naive();
+    Ok(call_signs
+        .iter()
+        .map(|call_sign| {
+            let licenses = licenses
+                .get(&call_sign.to_string())
+                .map_or(&[][..], Vec::as_slice);
+            evaluate(call_sign, licenses, operator_class, today)
+        })
+        .collect())
+}
+
+/// The most calls [`check_batch`] can check at once, one bind parameter each
+pub const BATCH_SIZE: usize = BIND_LIMIT;
+
+/// The most candidates a pattern can expand to, which is enough for every 2x2 in a district
+pub const MAX_CANDIDATES: usize = 26usize.pow(4);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternError {
+    /// The pattern had a character that can't be in a call sign
+    InvalidCharacter(char),
+    /// The pattern would expand to more than [`MAX_CANDIDATES`] calls
+    TooManyCandidates(usize),
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatternError::InvalidCharacter(c) => write!(f, "invalid character {:?} in pattern", c),
+            PatternError::TooManyCandidates(count) => write!(
+                f,
+                "pattern matches {} calls, more than the limit of {}",
+                count, MAX_CANDIDATES
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+/// Expands a pattern into every valid call sign it matches, in order
+///
+/// `?` matches any letter and `#` matches any digit, everything else matches itself. Matches
+/// that aren't valid call signs, like `AM1A` from `??1A`, are skipped.
+///
+/// # Examples
+/// ```
+/// use artemis::vanity::{expand_pattern, PatternError};
+///
+/// let calls = expand_pattern("w#xx").unwrap();
+/// assert_eq!(calls.len(), 10);
+/// assert_eq!(calls[0].to_string(), "W0XX");
+///
+/// assert_eq!(expand_pattern("K?4??").unwrap().len(), 26 * 26 * 26);
+/// assert_eq!(expand_pattern("A?1A").unwrap().len(), 12);
+/// assert_eq!(expand_pattern("W1A!"), Err(PatternError::InvalidCharacter('!')));
+/// assert!(matches!(
+///     expand_pattern("??#???"),
+///     Err(PatternError::TooManyCandidates(_))
+/// ));
+/// ```
+pub fn expand_pattern(pattern: &str) -> Result<Vec<CallSign>, PatternError> {
+    const LETTERS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    const DIGITS: &str = "0123456789";
+
+    let mut choices = Vec::new();
+    for c in pattern.trim().to_ascii_uppercase().chars() {
+        choices.push(match c {
+            '?' => LETTERS.to_string(),
+            '#' => DIGITS.to_string(),
+            c if c.is_ascii_alphanumeric() => c.to_string(),
+            c => return Err(PatternError::InvalidCharacter(c)),
+        });
+    }
+
+    let count = choices
+        .iter()
+        .try_fold(1usize, |count, choice| count.checked_mul(choice.len()))
+        .unwrap_or(usize::MAX);
+    if count > MAX_CANDIDATES {
+        return Err(PatternError::TooManyCandidates(count));
+    }
+
+    Ok(choices
+        .iter()
+        .map(|choice| choice.chars())
+        .multi_cartesian_product()
+        .filter_map(|chars| chars.into_iter().collect::<String>().parse().ok())
+        .collect())
+}
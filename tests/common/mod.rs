@@ -0,0 +1,53 @@
+//! Setup shared by the integration tests
+
+#![allow(dead_code)]
+
+use std::io::{Cursor, Write};
+
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::SqlitePool;
+use zip::write::FileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+/// An `HD.dat` line for an active license, with every field we don't care about left blank
+pub fn header(usi: u32, call_sign: &str) -> String {
+    let mut fields = vec![
+        "HD".to_string(),
+        usi.to_string(),
+        String::new(),
+        String::new(),
+        call_sign.to_string(),
+        "A".to_string(),
+        "HA".to_string(),
+    ];
+    fields.resize(59, String::new());
+    fields.join("|")
+}
+
+/// A dump's zip archive, held in memory, with each file's lines
+pub fn dump(files: &[(&str, Vec<String>)]) -> ZipArchive<Cursor<Vec<u8>>> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, lines) in files {
+        writer.start_file(*name, FileOptions::default()).unwrap();
+        for line in lines {
+            write!(writer, "{}\r\n", line).unwrap();
+        }
+    }
+    ZipArchive::new(writer.finish().unwrap()).unwrap()
+}
+
+/// An empty in-memory database, without any migrations applied
+pub async fn memory_database() -> SqlitePool {
+    SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with("sqlite::memory:".parse::<SqliteConnectOptions>().unwrap())
+        .await
+        .unwrap()
+}
+
+/// An in-memory database with every migration applied
+pub async fn database() -> SqlitePool {
+    let db = memory_database().await;
+    artemis::db::migrate(&db).await.unwrap();
+    db
+}
@@ -0,0 +1,98 @@
+use sqlx::SqlitePool;
+
+use artemis::load::{self, load_table};
+use artemis::{Comment, Header, SpecialCondition};
+
+mod common;
+
+use common::{database, dump, header};
+
+async fn count(db: &SqlitePool, table: &str, usi: u32) -> i64 {
+    sqlx::query_scalar(&format!(
+        "SELECT COUNT(*) FROM {} WHERE unique_system_identifier = ?",
+        table
+    ))
+    .bind(usi)
+    .fetch_one(db)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn daily_replaces_every_table_for_touched_licenses() {
+    let db = database().await;
+
+    let mut weekly = dump(&[
+        ("HD.dat", vec![header(1, "K1AAA"), header(2, "K2BBB")]),
+        (
+            "CO.dat",
+            vec![
+                "CO|1||K1AAA|01/02/2023|old comment||".to_string(),
+                "CO|2||K2BBB|01/02/2023|kept comment||".to_string(),
+            ],
+        ),
+        ("SC.dat", vec!["SC|1|||K1AAA|P|999||".to_string()]),
+    ]);
+    let mut conn = db.acquire().await.unwrap();
+    load_table::<Header>(&mut conn, &mut weekly, 1, true)
+        .await
+        .unwrap();
+    load_table::<Comment>(&mut conn, &mut weekly, 1, true)
+        .await
+        .unwrap();
+    load_table::<SpecialCondition>(&mut conn, &mut weekly, 1, true)
+        .await
+        .unwrap();
+
+    // License 1 has a new call sign and has lost its comment and special condition, so the
+    // daily dump has no CO.dat or SC.dat at all
+    let mut daily = dump(&[("HD.dat", vec![header(1, "K1CCC")])]);
+    let replaced = load::delete_licenses(&mut conn, &mut daily).await.unwrap();
+    assert_eq!(replaced, 1);
+    load_table::<Header>(&mut conn, &mut daily, 2, false)
+        .await
+        .unwrap();
+    load_table::<Comment>(&mut conn, &mut daily, 2, false)
+        .await
+        .unwrap();
+    load_table::<SpecialCondition>(&mut conn, &mut daily, 2, false)
+        .await
+        .unwrap();
+    drop(conn);
+
+    let call_sign: String =
+        sqlx::query_scalar("SELECT call_sign FROM headers WHERE unique_system_identifier = 1")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+    assert_eq!(call_sign, "K1CCC");
+    assert_eq!(count(&db, "headers", 1).await, 1);
+    assert_eq!(count(&db, "comments", 1).await, 0);
+    assert_eq!(count(&db, "special_conditions", 1).await, 0);
+
+    // Licenses the daily dump doesn't mention are left alone
+    assert_eq!(count(&db, "headers", 2).await, 1);
+    assert_eq!(count(&db, "comments", 2).await, 1);
+}
+
+#[tokio::test]
+async fn daily_deletes_licenses_whose_records_were_all_rejected() {
+    let db = database().await;
+
+    let mut weekly = dump(&[("HD.dat", vec![header(1, "K1AAA")])]);
+    let mut conn = db.acquire().await.unwrap();
+    load_table::<Header>(&mut conn, &mut weekly, 1, true)
+        .await
+        .unwrap();
+
+    // Too short to load, but the license is still touched by this dump
+    let mut daily = dump(&[("HD.dat", vec!["HD|1||".to_string()])]);
+    load::delete_licenses(&mut conn, &mut daily).await.unwrap();
+    let stats = load_table::<Header>(&mut conn, &mut daily, 2, false)
+        .await
+        .unwrap();
+    assert_eq!(stats.rejected, 1);
+    drop(conn);
+
+    assert_eq!(count(&db, "headers", 1).await, 0);
+}
@@ -0,0 +1,42 @@
+use artemis::license::{get_conditions, get_license};
+use artemis::load::load_table;
+use artemis::{Header, SpecialCondition};
+
+mod common;
+
+use common::{database, dump, header};
+
+#[tokio::test]
+async fn blank_special_condition_codes_are_none() {
+    let db = database().await;
+
+    let mut weekly = dump(&[
+        ("HD.dat", vec![header(1, "K1AAA")]),
+        (
+            "SC.dat",
+            vec![
+                "SC|1|||K1AAA|P|||".to_string(),
+                "SC|1|||K1AAA|P|999||".to_string(),
+            ],
+        ),
+    ]);
+    let mut conn = db.acquire().await.unwrap();
+    load_table::<Header>(&mut conn, &mut weekly, 1, true)
+        .await
+        .unwrap();
+    load_table::<SpecialCondition>(&mut conn, &mut weekly, 1, true)
+        .await
+        .unwrap();
+    drop(conn);
+
+    let conditions = get_conditions(&db, 1).await.unwrap();
+    let codes = conditions
+        .special_conditions
+        .iter()
+        .map(|condition| condition.code)
+        .collect::<Vec<_>>();
+    assert_eq!(codes, [Some(999), None]);
+    assert!(conditions.special_conditions[1].description.is_none());
+
+    assert!(get_license(&db, 1).await.unwrap().is_some());
+}
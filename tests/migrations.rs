@@ -0,0 +1,112 @@
+use chrono::Utc;
+use sqlx::SqlitePool;
+
+use artemis::db;
+
+mod common;
+
+use common::memory_database;
+
+/// The migrations a database made before entity search existed would have had
+const OLD_MIGRATIONS: &[(u32, &str)] = &[
+    (1, include_str!("../migrations/01-create-db.sql")),
+    (2, include_str!("../migrations/02-create-indexes.sql")),
+    (
+        3,
+        include_str!("../migrations/03-rename-vanity-relationship.sql"),
+    ),
+    (
+        4,
+        include_str!("../migrations/04-split-special-condition-codes.sql"),
+    ),
+];
+
+/// Builds a database the way an older update-db would have left it
+async fn old_database() -> SqlitePool {
+    let db = memory_database().await;
+    sqlx::query(
+        "CREATE TABLE schema_version (version INTEGER PRIMARY KEY, applied_at datetime NOT NULL)",
+    )
+    .execute(&db)
+    .await
+    .unwrap();
+    for (version, sql) in OLD_MIGRATIONS {
+        sqlx::query(sql).execute(&db).await.unwrap();
+        sqlx::query("INSERT INTO schema_version (version, applied_at) VALUES (?, ?)")
+            .bind(version)
+            .bind(Utc::now())
+            .execute(&db)
+            .await
+            .unwrap();
+    }
+    for (usi, last_name, operator_class) in [(1, "Ashcraft", "E"), (2, "Tymczak", "")] {
+        sqlx::query("INSERT INTO entities (unique_system_identifier, last_name) VALUES (?, ?)")
+            .bind(usi)
+            .bind(last_name)
+            .execute(&db)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO amateurs (unique_system_identifier, operator_class) VALUES (?, ?)",
+        )
+        .bind(usi)
+        .bind(operator_class)
+        .execute(&db)
+        .await
+        .unwrap();
+    }
+    db
+}
+
+#[tokio::test]
+async fn migrates_an_older_database() {
+    let db = old_database().await;
+    assert_eq!(db::schema_version(&db).await.unwrap(), 4);
+    assert!(db::require_latest_schema(&db).await.is_err());
+
+    db::migrate(&db).await.unwrap();
+    assert_eq!(
+        db::schema_version(&db).await.unwrap(),
+        db::latest_schema_version()
+    );
+    db::require_latest_schema(&db).await.unwrap();
+
+    // Existing entities get their Soundex codes filled in, the same way the loader computes them
+    let codes: Vec<(String,)> =
+        sqlx::query_as("SELECT last_name_soundex FROM entities ORDER BY unique_system_identifier")
+            .fetch_all(&db)
+            .await
+            .unwrap();
+    assert_eq!(codes, [("A261".to_string(),), ("T522".to_string(),)]);
+
+    // And are searchable
+    let (found,): (i64,) =
+        sqlx::query_as("SELECT rowid FROM entities_fts WHERE entities_fts MATCH 'tymczak'")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+    assert_eq!(found, 2);
+
+    let (blank,): (Option<String>,) =
+        sqlx::query_as("SELECT operator_class FROM amateurs WHERE unique_system_identifier = 2")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+    assert_eq!(blank, None);
+
+    // Migrating again doesn't do anything
+    db::migrate(&db).await.unwrap();
+}
+
+#[tokio::test]
+async fn checking_the_version_does_not_write() {
+    let db = memory_database().await;
+    assert_eq!(db::schema_version(&db).await.unwrap(), 0);
+    assert!(db::require_latest_schema(&db).await.is_err());
+
+    let (tables,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM sqlite_master")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+    assert_eq!(tables, 0);
+}
@@ -0,0 +1,129 @@
+use std::collections::HashSet;
+
+use sqlx::SqlitePool;
+
+use artemis::search::{self, Order, SearchParams, Sort};
+
+mod common;
+
+use common::database;
+
+/// Licensees who all share a last name, and pairs of whom share a grant date
+const LICENSEES: &[(u32, &str, &str)] = &[
+    (1, "K1AAA", "2020-01-01"),
+    (2, "K1AAB", "2020-01-01"),
+    (3, "K1AAC", "2021-06-15"),
+    (4, "K1AAD", "2021-06-15"),
+    (5, "K1AAE", "2021-06-15"),
+    (6, "K1AAF", "2022-03-10"),
+    (7, "K1AAG", "2022-03-10"),
+];
+
+async fn load_licensees(db: &SqlitePool) {
+    for (usi, call_sign, grant_date) in LICENSEES {
+        sqlx::query(
+            "INSERT INTO entities
+                (unique_system_identifier, call_sign, frn, first_name, mi, last_name, city, state)
+            VALUES (?, ?, ?, '', '', 'Smith', '', '')",
+        )
+        .bind(usi)
+        .bind(call_sign)
+        .bind(format!("{:010}", usi))
+        .execute(db)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO amateurs (unique_system_identifier, call_sign, operator_class)
+            VALUES (?, ?, 'E')",
+        )
+        .bind(usi)
+        .bind(call_sign)
+        .execute(db)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO headers
+                (unique_system_identifier, call_sign, license_status, grant_date, expired_date,
+                cancellation_date)
+            VALUES (?, ?, 'A', ?, '', '')",
+        )
+        .bind(usi)
+        .bind(call_sign)
+        .bind(grant_date)
+        .execute(db)
+        .await
+        .unwrap();
+    }
+}
+
+/// Follows the cursors from the first page to the last, returning every FRN in order
+async fn page_through(db: &SqlitePool, sort: Sort, order: Order) -> Vec<String> {
+    let mut frns = Vec::new();
+    let mut cursor = None;
+    loop {
+        let params = SearchParams {
+            sort: Some(sort),
+            order: Some(order),
+            limit: Some(2),
+            cursor,
+            ..Default::default()
+        };
+        let page = search::search(db, &params).await.unwrap();
+        assert_eq!(page.total, LICENSEES.len() as i64);
+        assert!(page.results.len() <= 2);
+        frns.extend(page.results.into_iter().map(|result| result.frn));
+        cursor = page.next_cursor;
+        if cursor.is_none() {
+            return frns;
+        }
+    }
+}
+
+#[tokio::test]
+async fn paging_through_ties_skips_and_repeats_nothing() {
+    let db = database().await;
+    load_licensees(&db).await;
+
+    for (sort, order) in [
+        (Sort::LastName, Order::Asc),
+        (Sort::LastName, Order::Desc),
+        (Sort::GrantDate, Order::Asc),
+        (Sort::GrantDate, Order::Desc),
+    ] {
+        let frns = page_through(&db, sort, order).await;
+        assert_eq!(frns.len(), LICENSEES.len(), "{:?} {:?}", sort, order);
+        assert_eq!(
+            frns.iter().collect::<HashSet<_>>().len(),
+            LICENSEES.len(),
+            "{:?} {:?}",
+            sort,
+            order
+        );
+    }
+
+    // Ties are broken by FRN, in the same direction as the sort
+    assert_eq!(
+        page_through(&db, Sort::GrantDate, Order::Desc).await,
+        [
+            "0000000007",
+            "0000000006",
+            "0000000005",
+            "0000000004",
+            "0000000003",
+            "0000000002",
+            "0000000001"
+        ]
+    );
+    assert_eq!(
+        page_through(&db, Sort::LastName, Order::Asc).await,
+        [
+            "0000000001",
+            "0000000002",
+            "0000000003",
+            "0000000004",
+            "0000000005",
+            "0000000006",
+            "0000000007"
+        ]
+    );
+}
@@ -0,0 +1,71 @@
+use chrono::{Local, Months, NaiveDate};
+
+use artemis::callsign::CallSign;
+use artemis::load::load_table;
+use artemis::vanity::{self, Reason};
+use artemis::Header;
+
+mod common;
+
+use common::{database, dump, header};
+
+/// An `HD.dat` line for a license that expired on `expired`
+fn expired_header(usi: u32, call_sign: &str, expired: NaiveDate) -> String {
+    let mut fields = header(usi, call_sign)
+        .split('|')
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    fields[5] = "E".to_string();
+    fields[8] = expired.format("%m/%d/%Y").to_string();
+    fields.join("|")
+}
+
+#[tokio::test]
+async fn expired_calls_are_held_for_the_grace_period() {
+    let db = database().await;
+    let today = Local::now().date_naive();
+    let recently = today - Months::new(6);
+    let long_ago = today - Months::new(36);
+
+    let mut weekly = dump(&[(
+        "HD.dat",
+        vec![
+            expired_header(1, "W1AB", recently),
+            expired_header(2, "W1CD", long_ago),
+            header(3, "W1EF"),
+        ],
+    )]);
+    let mut conn = db.acquire().await.unwrap();
+    load_table::<Header>(&mut conn, &mut weekly, 1, true)
+        .await
+        .unwrap();
+    drop(conn);
+
+    let calls = ["W1AB", "W1CD", "W1EF", "W1GH"]
+        .iter()
+        .map(|call| call.parse::<CallSign>().unwrap())
+        .collect::<Vec<_>>();
+    let availability = vanity::check_batch(&db, &calls, None).await.unwrap();
+    let reasons = availability
+        .iter()
+        .map(|availability| availability.reason)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        reasons,
+        [
+            Reason::GracePeriod,
+            Reason::Available,
+            Reason::Active,
+            Reason::Available
+        ]
+    );
+
+    // The call held back frees up the day after the two years are up
+    assert!(!availability[0].available);
+    assert_eq!(
+        availability[0].available_date,
+        (recently + Months::new(24)).succ_opt()
+    );
+    assert!(availability[1].available);
+    assert_eq!(availability[1].available_date, None);
+}